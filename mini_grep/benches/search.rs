@@ -0,0 +1,95 @@
+//! mini_grep 的性能测试, 运行 `cargo bench -p pandastd-mini-grep`
+//!
+//! 稳定版 Rust 不支持 `#[bench]`, 所以这里用 `Instant` 自己计时:
+//! 每个用例先预热一次, 再重复执行若干次, 输出平均耗时和吞吐量.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use pandastd_mini_grep::{fast_search, search};
+
+// 每个用例重复执行的次数
+const ROUNDS: u32 = 20;
+
+fn bench<T>(name: &str, bytes: usize, mut f: impl FnMut() -> T) {
+    black_box(f());
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    let per_round = start.elapsed() / ROUNDS;
+    let throughput = bytes as f64 / per_round.as_secs_f64() / (1024.0 * 1024.0);
+    println!(
+        "{:<40} {:>12} {:>10.1} MiB/s",
+        name,
+        format_duration(per_round),
+        throughput
+    );
+}
+
+fn format_duration(d: Duration) -> String {
+    format!("{:.3} ms", d.as_secs_f64() * 1000.0)
+}
+
+// 生成测试文本: 大部分是 ASCII, 夹杂一些需要折叠的字符, 匹配的行比较少
+fn corpus() -> String {
+    let lines = [
+        "Rust: safe, fast, productive. Pick three.",
+        "The quick brown fox jumps over the lazy dog",
+        "Die Straße ist lang und die STRASSE ist breit",
+        "ΣΊΣΥΦΟΣ rolls the stone up the hill again",
+        "error[E0499]: cannot borrow `x` as mutable more than once",
+        "İstanbul ve Izmir büyük şehirlerdir",
+        "How dreary to be somebody! How public, like a frog",
+    ];
+    let mut text = String::new();
+    for i in 0..100_000 {
+        text.push_str(lines[i % lines.len()]);
+        text.push('\n');
+    }
+    text
+}
+
+// 改造之前的 search: 每一行都调用 to_lowercase 生成一个新的字符串
+fn lowercase_search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let query = query.to_lowercase();
+    contents
+        .lines()
+        .filter(|line| line.to_lowercase().contains(&query))
+        .collect()
+}
+
+fn main() {
+    let text = corpus();
+    println!(
+        "corpus: {} lines, {} bytes\n",
+        text.lines().count(),
+        text.len()
+    );
+
+    println!("# ignore case");
+    for query in ["PRODUCTIVE", "strasse", "mutable more"] {
+        bench(&format!("lowercase_search {:?}", query), text.len(), || {
+            lowercase_search(query, &text)
+        });
+        bench(
+            &format!("search (case folding) {:?}", query),
+            text.len(),
+            || search(true, query, &text).unwrap(),
+        );
+    }
+
+    println!("\n# case sensitive");
+    for query in ["productive", "x", "cannot borrow `x` as mutable"] {
+        assert_eq!(
+            fast_search(query, &text).unwrap(),
+            search(false, query, &text).unwrap()
+        );
+        bench(&format!("search {:?}", query), text.len(), || {
+            search(false, query, &text).unwrap()
+        });
+        bench(&format!("fast_search {:?}", query), text.len(), || {
+            fast_search(query, &text).unwrap()
+        });
+    }
+}
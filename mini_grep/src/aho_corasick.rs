@@ -0,0 +1,248 @@
+//! 同时查找多个字面模式, 使用 Aho-Corasick 自动机
+//!
+//! 所有模式先插入一棵字典树 (trie), 再用广度优先遍历给每个状态计算失败指针:
+//! 当前字符走不下去时, 跳到 "当前已匹配文本的最长真后缀" 对应的状态继续匹配.
+//! 这样文本中的每个字符只需要处理一次, 搜索时间和模式的数量无关, 适合几千个关键词的黑名单.
+//!
+//! 自动机以字符为单位工作. 忽略大小写时, 模式和文本都先做 Unicode 大小写折叠, 规则见 [`crate::fold`].
+
+use crate::fold::{fold, Fold};
+
+// 根状态的下标
+const ROOT: usize = 0;
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    // 按字符排序的转移, 查找时二分
+    next: Vec<(char, usize)>,
+    // 失败指针
+    fail: usize,
+    // 在这个状态结束的模式的字符数
+    out: Option<usize>,
+    // 字典后缀链接: 沿着失败指针能到达的, 最近的有模式结束的状态.
+    // 同一个位置结束的所有模式从长到短就是 out 加上沿着这个链接的每一个 out
+    dict: Option<usize>,
+}
+
+/// 多模式查找器, 返回最靠左的匹配, 开始位置相同时返回最长的那个
+///
+/// # Example
+/// ```
+/// use pandastd_mini_grep::aho_corasick::AhoCorasick;
+///
+/// let finder = AhoCorasick::new(&["fast", "safe", "productive"], false);
+/// assert_eq!(finder.find("Rust: fast, safe."), Some((6, 10)));
+/// assert_eq!(finder.find("Pick three."), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    states: Vec<State>,
+    ignore_case: bool,
+    // 最长模式的字符数, 用来判断什么时候可以提前结束搜索
+    max_len: usize,
+}
+
+impl AhoCorasick {
+    /// patterns 中不能有空字符串, 空模式匹配每一个位置, 应该由调用者单独处理
+    pub fn new<S: AsRef<str>>(patterns: &[S], ignore_case: bool) -> AhoCorasick {
+        let mut finder = AhoCorasick {
+            states: vec![State::default()],
+            ignore_case,
+            max_len: 0,
+        };
+        for pattern in patterns {
+            finder.insert(pattern.as_ref());
+        }
+        for state in &mut finder.states {
+            state.next.sort_unstable();
+        }
+        finder.build_fail();
+        finder
+    }
+
+    fn symbols(&self, c: char) -> Fold {
+        if self.ignore_case {
+            fold(c)
+        } else {
+            Fold::from(c)
+        }
+    }
+
+    fn insert(&mut self, pattern: &str) {
+        let mut state = ROOT;
+        let mut len = 0;
+        for c in pattern
+            .chars()
+            .flat_map(|c| self.symbols(c))
+            .collect::<Vec<_>>()
+        {
+            state = match self.states[state].next.iter().find(|&&(s, _)| s == c) {
+                Some(&(_, to)) => to,
+                None => {
+                    self.states.push(State::default());
+                    let to = self.states.len() - 1;
+                    self.states[state].next.push((c, to));
+                    to
+                }
+            };
+            len += 1;
+        }
+        if len > 0 {
+            self.states[state].out = Some(len);
+            self.max_len = self.max_len.max(len);
+        }
+    }
+
+    // 按广度优先的顺序计算失败指针, 保证处理一个状态时, 比它浅的状态都已经处理完了
+    fn build_fail(&mut self) {
+        let mut queue = std::collections::VecDeque::new();
+        queue.extend(self.states[ROOT].next.iter().map(|&(_, to)| to));
+        while let Some(state) = queue.pop_front() {
+            for i in 0..self.states[state].next.len() {
+                let (c, to) = self.states[state].next[i];
+                let mut fail = self.states[state].fail;
+                let target = loop {
+                    if let Some(target) = self.goto(fail, c) {
+                        break target;
+                    }
+                    if fail == ROOT {
+                        break ROOT;
+                    }
+                    fail = self.states[fail].fail;
+                };
+                self.states[to].fail = target;
+                // 失败指针指向的状态一定比 to 浅, 它的 dict 已经算好了
+                self.states[to].dict = match self.states[target].out {
+                    Some(_) => Some(target),
+                    None => self.states[target].dict,
+                };
+                queue.push_back(to);
+            }
+        }
+    }
+
+    fn goto(&self, state: usize, c: char) -> Option<usize> {
+        let next = &self.states[state].next;
+        next.binary_search_by_key(&c, |&(s, _)| s)
+            .ok()
+            .map(|i| next[i].1)
+    }
+
+    fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(to) = self.goto(state, c) {
+                return to;
+            }
+            if state == ROOT {
+                return ROOT;
+            }
+            state = self.states[state].fail;
+        }
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// 返回最靠左的匹配在 haystack 中的字节区间
+    ///
+    /// 和 [`crate::fold::CaseInsensitive`] 一样, 匹配必须从一个字符开始, 在一个字符结束
+    pub fn find(&self, haystack: &str) -> Option<(usize, usize)> {
+        let mut state = ROOT;
+        // 每个折叠后的字符来自哪个原始字符: (原始字符的开始位置, 是否是折叠结果的第一个字符)
+        let mut origins = Vec::new();
+        // 目前最好的匹配: (开始的字符下标, 开始位置, 结束位置)
+        let mut best: Option<(usize, usize, usize)> = None;
+        for (pos, c) in haystack.char_indices() {
+            // 之后结束的匹配都不可能比 best 开始得更早
+            if best.is_some_and(|(first, _, _)| origins.len() >= first + self.max_len) {
+                break;
+            }
+            let symbols = self.symbols(c);
+            let last = symbols.len() - 1;
+            for (i, symbol) in symbols.enumerate() {
+                origins.push((pos, i == 0));
+                state = self.step(state, symbol);
+                if i < last {
+                    continue;
+                }
+                // 在这里结束的模式从长到短检查, 最长的那个可能从一个字符的中间开始 (例如 ß 折叠成 ss),
+                // 这时还要看更短的模式, 否则多加一个模式反而会漏掉原来能找到的匹配
+                let mut at = Some(state);
+                while let Some(s) = at {
+                    if let Some(len) = self.states[s].out {
+                        let first = origins.len() - len;
+                        let (start, whole) = origins[first];
+                        if whole {
+                            // 同一个开始位置, 后结束的匹配更长
+                            if best.is_none_or(|(f, _, _)| first <= f) {
+                                best = Some((first, start, pos + c.len_utf8()));
+                            }
+                            break;
+                        }
+                    }
+                    at = self.states[s].dict;
+                }
+            }
+        }
+        best.map(|(_, start, end)| (start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let find =
+            |patterns: &[&str], haystack: &str| AhoCorasick::new(patterns, false).find(haystack);
+        assert_eq!(find(&["he", "she", "his", "hers"], "ushers"), Some((1, 4)));
+        assert_eq!(find(&["b", "abc"], "abc"), Some((0, 3)));
+        // 开始位置相同时取最长的
+        assert_eq!(find(&["ab", "abcd", "abc"], "xabcd"), Some((1, 5)));
+        assert_eq!(find(&["bcd", "a"], "abcd"), Some((0, 1)));
+        assert_eq!(find(&["世界", "界"], "你好世界"), Some((6, 12)));
+        assert_eq!(find(&["abd"], "abababd"), Some((4, 7)));
+        assert_eq!(find(&["abc"], "ab"), None);
+        assert_eq!(find(&[], "abc"), None);
+    }
+
+    #[test]
+    fn test_find_ignore_case() {
+        let find =
+            |patterns: &[&str], haystack: &str| AhoCorasick::new(patterns, true).find(haystack);
+        assert_eq!(find(&["STRASSE", "weg"], "die Straße"), Some((4, 11)));
+        assert_eq!(find(&["WEG", "x"], "İİX Weg"), Some((4, 5)));
+        // 不能只匹配 ß 折叠结果的一半
+        assert_eq!(find(&["s", "sa"], "ßa"), None);
+        // 最长的 sa 从 ß 的中间开始时, 还要检查同一个位置结束的更短的 a
+        assert_eq!(find(&["sa", "a"], "ßa"), Some((2, 3)));
+        assert_eq!(find(&["i"], "ı"), None);
+    }
+
+    #[test]
+    fn test_find_matches_naive_search() {
+        let patterns = ["a", "ab", "bab", "bc", "bca", "c", "caa"];
+        let texts = ["abccab", "bcbcab", "aab", "xyz", "cbabc", "bcaa"];
+        for text in texts {
+            // 暴力查找: 开始位置最小, 其次最长
+            let expected = (0..text.len())
+                .filter_map(|start| {
+                    let end = patterns
+                        .iter()
+                        .filter(|p| text[start..].starts_with(*p))
+                        .map(|p| start + p.len())
+                        .max()?;
+                    Some((start, end))
+                })
+                .next();
+            assert_eq!(
+                AhoCorasick::new(&patterns, false).find(text),
+                expected,
+                "{}",
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! 输出的颜色, 使用 ANSI 转义序列
+//!
+//! 每一部分的颜色是一个 SGR 参数, 例如 `01;31` 表示粗体红色, 可以通过环境变量 `MINI_GREP_COLORS` 修改,
+//! 格式和 GNU grep 的 `GREP_COLORS` 相同: 用 `:` 分隔的 `名字=SGR参数`, 例如 `ms=01;32:fn=34`.
+//!
+//! - `ms`: 匹配的部分, `mt` 是它的别名
+//! - `fn`: 文件路径
+//! - `ln`: 行号和列号
+//! - `se`: 分隔符 `:`, `-` 和 `--`
+//!
+//! 值为空时这一部分不加颜色, 不认识的名字和不合法的值会被忽略.
+//!
+//! `--color=auto` 时只有标准输出是终端才加颜色, 设置了非空的 `NO_COLOR` 环境变量
+//! (见 <https://no-color.org>) 或者 `TERM=dumb` 时也不加. `--color=always` 总是加颜色.
+
+use std::env;
+use std::fmt::Display;
+use std::io::{Result, Write};
+
+use crate::config::ColorChoice;
+
+/// 各部分使用的 SGR 参数, 为空时不加颜色
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors {
+    pub matched: String,
+    pub path: String,
+    pub line_number: String,
+    pub separator: String,
+}
+
+// 和 GNU grep 的默认颜色一样
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            matched: String::from("01;31"),
+            path: String::from("35"),
+            line_number: String::from("32"),
+            separator: String::from("36"),
+        }
+    }
+}
+
+impl Colors {
+    /// 在默认颜色的基础上, 按照 spec 修改
+    pub fn parse(spec: &str) -> Colors {
+        let mut colors = Colors::default();
+        for item in spec.split(':') {
+            let Some((name, value)) = item.split_once('=') else {
+                continue;
+            };
+            // 只允许数字和分号, 避免把其他转义序列写进输出
+            if !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                continue;
+            }
+            let target = match name {
+                "ms" | "mt" => &mut colors.matched,
+                "fn" => &mut colors.path,
+                "ln" => &mut colors.line_number,
+                "se" => &mut colors.separator,
+                _ => continue,
+            };
+            *target = value.to_string();
+        }
+        colors
+    }
+
+    /// 读取环境变量 MINI_GREP_COLORS, 没有设置时使用默认颜色
+    pub fn from_env() -> Colors {
+        Colors::parse(&env::var("MINI_GREP_COLORS").unwrap_or_default())
+    }
+}
+
+/// 是否要输出颜色, is_terminal 是输出是否是终端
+pub fn enabled(choice: ColorChoice, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            let dumb = env::var_os("TERM").is_some_and(|v| v == "dumb");
+            is_terminal && !no_color && !dumb
+        }
+    }
+}
+
+/// 把 text 写进 out, color 为 None 或者空字符串时不加颜色
+pub fn paint(out: &mut impl Write, color: Option<&str>, text: impl Display) -> Result<()> {
+    match color {
+        // \x1b[K 清除到行尾, 避免换行时背景色延伸到整行
+        Some(color) if !color.is_empty() => {
+            write!(out, "\x1b[{}m\x1b[K{}\x1b[m\x1b[K", color, text)
+        }
+        _ => write!(out, "{}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Colors::parse(""), Colors::default());
+        let colors = Colors::parse("mt=01;32:fn=:ln=x1b:se=1:sl=7:bogus");
+        assert_eq!(colors.matched, "01;32");
+        assert_eq!(colors.path, "");
+        // 不合法的值被忽略, 保留默认颜色
+        assert_eq!(colors.line_number, "32");
+        assert_eq!(colors.separator, "1");
+    }
+
+    #[test]
+    fn test_paint() {
+        let mut out = Vec::new();
+        paint(&mut out, Some("01;31"), "fast").unwrap();
+        paint(&mut out, Some(""), "-").unwrap();
+        paint(&mut out, None, "er").unwrap();
+        assert_eq!(out, b"\x1b[01;31m\x1b[Kfast\x1b[m\x1b[K-er");
+    }
+
+    #[test]
+    fn test_enabled() {
+        assert!(enabled(ColorChoice::Always, false));
+        assert!(!enabled(ColorChoice::Never, true));
+        assert!(!enabled(ColorChoice::Auto, false));
+    }
+}
@@ -0,0 +1,1068 @@
+//! 命令行参数解析
+//!
+//! 支持短参数 (`-i`, 可以合并成 `-in`), 长参数 (`--ignore-case`), 带值的参数 (`-e PATTERN`, `-ePATTERN`,
+//! `--regexp=PATTERN`), 以及用 `--` 结束参数解析. 所有选项都保存在 [`Config`] 的公有字段里,
+//! 库的使用者也可以不经过命令行, 直接构造 Config.
+//!
+//! 默认选项可以写在环境变量 `MINI_GREP_CONFIG` 指向的配置文件里, 每行一个参数, 例如:
+//!
+//! ```text
+//! # 空行和以 # 开头的行会被忽略
+//! --ignore-case
+//! --color=always
+//! -j4
+//! ```
+//!
+//! 配置文件中的参数在命令行参数之前解析, 所以命令行总是优先: 带值的选项后面的覆盖前面的,
+//! 开关可以用相反的选项关掉, 例如用 `-s` 关掉配置文件中的 `--ignore-case`.
+//! 环境变量 `IGNORE_CASE` 的优先级最低. `--no-config` 不读取配置文件, `--debug-config` 输出合并之后的 Config.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::replace::Replacement;
+use crate::Error;
+
+/// `--help` 输出的帮助信息
+pub const USAGE: &str = "\
+Usage: mini_grep [OPTIONS] QUERY [PATH]
+       mini_grep [OPTIONS] -e PATTERN... [PATH]
+       mini_grep [OPTIONS] -f FILE... [PATH]
+       mini_grep index [OPTIONS] [DIR]
+       mini_grep --interactive [OPTIONS] [PATH]
+
+Search for QUERY in PATH. PATH may be a file or a directory, which is searched recursively.
+When PATH is '-' or omitted, standard input is searched.
+
+Default options are read from the file named by the MINI_GREP_CONFIG env var, one argument per
+line; empty lines and lines starting with '#' are ignored. Options on the command line come
+after them, so they take precedence.
+
+--interactive opens a terminal UI on PATH (default: '.'): matching lines refresh as you type the
+query, Up/Down (or Ctrl-P/Ctrl-N) move the selection, Enter prints the selected 'path:line' and
+Esc or Ctrl-C quits. Matching options such as -i, --regex and --fuzzy apply; with --fuzzy the
+closest lines come first.
+
+'mini_grep index' builds or incrementally updates a trigram index of DIR (default: '.') in
+DIR/.mini_grep_index; the walk options below choose which files are indexed. To search for the
+word 'index' itself, use '-e index' or '-- index'.
+
+Options:
+  -i, --ignore-case         ignore case distinctions (falls back to the IGNORE_CASE env var)
+  -s, --case-sensitive      don't ignore case distinctions, overrides -i and IGNORE_CASE
+  -n, --line-number         prefix each line of output with its line number
+  -v, --invert-match        select non-matching lines
+  -w, --word-regexp         match only whole words: the match must not be preceded or followed by
+                            a letter, digit or underscore (Unicode-aware)
+  -x, --line-regexp         match only whole lines, takes precedence over -w
+  -U, --multiline           let matches span lines: the query may contain a newline (\n with
+                            --regex, where ^ and $ also match at line boundaries); every line a
+                            match covers is printed. Each input is read into memory as a whole
+  -c, --count               print only a count of selected lines per file
+  -l, --files-with-matches  print only the names of files with selected lines
+  -L, --files-without-match print only the names of files without selected lines
+  -o, --only-matching       print only the matched parts of each line, one per line
+  -q, --quiet               print nothing, exit with status 0 if any line is selected
+      --json                print one JSON object per line for each event (begin, match, context,
+                            end and summary), see the `json` module docs for the format
+      --column              prefix each matching line with the column of the first match
+      --color WHEN          highlight matches, file names and line numbers: auto, always or never
+                            (default: auto, which respects NO_COLOR; colors are set by
+                            MINI_GREP_COLORS, e.g. 'ms=01;31:fn=35:ln=32:se=36')
+      --replace TEXT        print selected lines with every match replaced by TEXT, where $0 is
+                            the whole match and $$ is a literal '$'; with -o print only the
+                            replaced matches
+      --in-place            with --replace, rewrite the searched files instead of printing; each
+                            changed file is replaced atomically through a temporary file
+      --backup SUFFIX       with --in-place, keep a copy of each changed file as FILE + SUFFIX
+  -A, --after-context NUM   print NUM lines of trailing context
+  -B, --before-context NUM  print NUM lines of leading context
+  -C, --context NUM         print NUM lines of leading and trailing context
+  -e, --regexp PATTERN      use PATTERN as the query, useful when it starts with '-';
+                            may be repeated, a line matches if any pattern matches
+  -f, --file FILE           read patterns from FILE, one per line; may be repeated
+      --regex               treat the query as a regular expression
+      --fuzzy K             match lines containing a substring within Levenshtein distance K of
+                            the query (insertions, deletions and substitutions cost 1)
+      --sort ORDER          score: print selected lines by match score, best first (the edit
+                            distance with --fuzzy, otherwise input order), buffered until the end;
+                            path, modified or matches: search files in a directory by path, by
+                            modification time (oldest first) or print them by number of selected
+                            lines (most first)
+      --stats               print statistics to stderr at the end: files searched and matched,
+                            selected lines, bytes searched and elapsed time. With --json they
+                            are a 'stats' event on stdout instead
+      --hidden              search hidden files and directories
+      --binary              also search binary files in directories, which are skipped by default
+      --binary-files TYPE   how to search input containing NUL bytes: 'binary' (default) prints
+                            'Binary file X matches' instead of the matching lines, 'text' searches
+                            it like any other input (and implies --binary), 'without-match'
+                            treats it as not matching
+  -z, --search-zip          decompress gzip and zlib input while searching; other input is
+                            searched as is
+      --no-ignore           don't respect .gitignore, .ignore and the global git excludes file
+      --include GLOB        search only files whose name matches GLOB (the path relative to PATH
+                            when GLOB contains '/'); may be repeated
+      --exclude GLOB        skip files and directories matching GLOB; may be repeated
+  -t, --type TYPE           search only files of TYPE, e.g. 'rust' for *.rs; may be repeated
+      --type-list           print the supported file types and their globs, then exit
+  -j, --threads NUM         number of worker threads for directory searches (default: CPU count)
+      --index               use the index of PATH or its nearest parent directory to skip files that
+                            can't match; files changed since indexing are always searched
+      --interactive         open a terminal UI and search PATH as you type, see below
+  -h, --help                print this help and exit
+  -V, --version             print version information and exit
+      --no-config           don't read the MINI_GREP_CONFIG file
+      --debug-config        print the effective configuration after merging the config file, the
+                            environment and the command line, then exit
+      --                    stop parsing options, the rest are QUERY and PATH
+
+Exit status is 0 if any line is selected, 1 if none is selected and 2 if an error occurred.
+Unreadable files in a directory search are reported and skipped, but still make the status 2.
+";
+
+// 所有支持的参数: (短参数, 长参数, 是否需要一个值)
+// 短参数最终都会转换成长参数, 由 Config::set 统一处理
+const OPTIONS: &[(Option<char>, &str, bool)] = &[
+    (Some('i'), "ignore-case", false),
+    (Some('s'), "case-sensitive", false),
+    (Some('n'), "line-number", false),
+    (Some('v'), "invert-match", false),
+    (Some('w'), "word-regexp", false),
+    (Some('x'), "line-regexp", false),
+    (Some('U'), "multiline", false),
+    (Some('c'), "count", false),
+    (Some('l'), "files-with-matches", false),
+    (Some('L'), "files-without-match", false),
+    (Some('o'), "only-matching", false),
+    (Some('q'), "quiet", false),
+    (None, "json", false),
+    (None, "column", false),
+    (None, "color", true),
+    (None, "replace", true),
+    (None, "in-place", false),
+    (None, "backup", true),
+    (Some('A'), "after-context", true),
+    (Some('B'), "before-context", true),
+    (Some('C'), "context", true),
+    (Some('e'), "regexp", true),
+    (Some('f'), "file", true),
+    (None, "regex", false),
+    (None, "fuzzy", true),
+    (None, "sort", true),
+    (None, "stats", false),
+    (None, "hidden", false),
+    (None, "binary", false),
+    (None, "binary-files", true),
+    (Some('z'), "search-zip", false),
+    (None, "no-ignore", false),
+    (None, "include", true),
+    (None, "exclude", true),
+    (Some('t'), "type", true),
+    (None, "type-list", false),
+    (Some('j'), "threads", true),
+    (None, "index", false),
+    (Some('h'), "help", false),
+    (Some('V'), "version", false),
+    (None, "no-config", false),
+    (None, "debug-config", false),
+    (None, "interactive", false),
+];
+
+/// Config 结构体, 用于存储命令行参数
+///
+/// # parameters
+/// 1. query: 查询字符串
+/// 2. path: 文件或目录路径, 为 `-` 时从标准输入读取, 命令行中省略 path 时也是 `-`
+/// 3. ignore_case: 是否忽略大小写, 默认为 false, 可以通过 -i 或者环境变量 IGNORE_CASE 设置, -s 设置为 false
+///    `ignore_case 为 1, true, TRUE, True 时为 true, 其他值为 false`
+/// 4. mode: 匹配模式, 默认为字面匹配, 传入 --regex 时把 query 当作正则表达式, 传入 --fuzzy 时模糊匹配
+/// 5. hidden: path 是目录时是否搜索隐藏文件, 传入 --hidden 时为 true
+/// 6. binary: path 是目录时是否搜索二进制文件, 传入 --binary 时为 true
+/// 7. line_number: 输出时是否带上行号, 传入 -n 时为 true
+/// 8. invert: 是否反转匹配, 只输出不匹配的行, 传入 -v 时为 true
+/// 9. output: 输出什么, 默认输出被选中的整行, 由 -c / -l / -L / -o / -q / --json 设置, 见 [`OutputMode`]
+/// 10. column: 输出时是否带上第一个匹配的列号 (从 1 开始的字节列), 传入 --column 时为 true
+/// 11. after_context / before_context: 匹配行之后 / 之前额外输出的上下文行数, 由 -A / -B / -C 设置
+/// 12. jobs: 搜索目录时使用的线程数, 为 0 时使用和 CPU 核数相同的线程数, 由 -j 设置
+/// 13. help / version / type_list: 传入 --help / --version / --type-list 时为 true, 此时不要求 query 和 path
+/// 14. patterns: 由 -e 和 -f 指定的多个模式, 一行只要匹配其中任意一个就算匹配.
+///     为 Some 时忽略 query, 为 Some 但列表为空时 (例如 -f 读到空文件) 不匹配任何行
+/// 15. color: 是否输出颜色, 由 --color 设置, 见 [`ColorChoice`]
+/// 16. no_ignore: path 是目录时是否不遵守 .gitignore 等忽略文件, 传入 --no-ignore 时为 true
+/// 17. include / exclude: path 是目录时只搜索 / 跳过和这些通配符匹配的文件, 由 --include / --exclude 设置
+/// 18. types: path 是目录时只搜索这些类型的文件, 由 -t 设置, 支持的类型见 [`crate::walk::TYPES`]
+/// 19. word_regexp / line_regexp: 匹配必须是完整的单词 / 整行, 由 -w / -x 设置, 两个都传时 -x 优先
+/// 20. replace: 输出前把匹配替换成的内容, 由 --replace 设置, 语法见 [`crate::replace`]
+/// 21. in_place / backup: 是否直接改写文件而不输出, 以及备份文件的后缀, 由 --in-place / --backup 设置.
+///     in_place 要求 replace 不为 None, 并且 path 不是标准输入
+/// 22. decompress: 是否把 gzip / zlib 格式的输入解压后再搜索, 传入 -z 时为 true, 见 [`crate::inflate`]
+/// 23. sort: 被选中的行的输出顺序, 为 None 时按搜索到的顺序输出, 由 --sort 设置, 见 [`SortBy`]
+/// 24. build_index: 是否是 `index` 子命令, 此时不要求 query, path 是建立索引的目录, 省略时是 `.`
+/// 25. use_index: path 是目录时是否使用索引挑选需要搜索的文件, 传入 --index 时为 true, 见 [`crate::index`]
+/// 26. binary_files: 怎样搜索含有 NUL 字节的输入, 由 --binary-files 设置, 见 [`BinaryFiles`]
+/// 27. multiline: 匹配是否可以跨行, 传入 -U 时为 true. 此时被匹配覆盖的每一行都是被选中的行, 不能和 -x / --in-place 一起使用
+/// 28. config_file: 读取了的配置文件, 没有设置 MINI_GREP_CONFIG 或者传入 --no-config 时为 None
+/// 29. debug_config: 传入 --debug-config 时为 true, 此时不要求 query, 只输出合并之后的 Config
+/// 30. stats: 搜索结束后是否输出统计信息, 传入 --stats 时为 true, 见 [`crate::SearchStats`]
+/// 31. interactive: 是否打开交互式界面, 传入 --interactive 时为 true. 此时不要求 query, path 省略时是 `.`,
+///     见 [`crate::interactive`]
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub query: String,
+    pub path: String,
+    pub ignore_case: bool,
+    pub mode: MatchMode,
+    pub hidden: bool,
+    pub binary: bool,
+    pub line_number: bool,
+    pub invert: bool,
+    pub output: OutputMode,
+    pub column: bool,
+    pub after_context: usize,
+    pub before_context: usize,
+    pub jobs: usize,
+    pub help: bool,
+    pub version: bool,
+    pub patterns: Option<Vec<String>>,
+    pub color: ColorChoice,
+    pub no_ignore: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub types: Vec<String>,
+    pub type_list: bool,
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    pub replace: Option<Replacement>,
+    pub in_place: bool,
+    pub backup: Option<String>,
+    pub decompress: bool,
+    pub sort: Option<SortBy>,
+    pub build_index: bool,
+    pub use_index: bool,
+    pub binary_files: BinaryFiles,
+    pub multiline: bool,
+    pub config_file: Option<PathBuf>,
+    pub debug_config: bool,
+    pub stats: bool,
+    pub interactive: bool,
+}
+
+/// 查询字符串的匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// 普通的子串匹配
+    #[default]
+    Literal,
+    /// 把 query 编译成正则表达式, 语法见 [`crate::regex`] 模块
+    Regex,
+    /// 匹配和 query 的编辑距离不超过这个值的子串, 见 [`crate::fuzzy`] 模块.
+    /// 同时传了 --regex 和 --fuzzy 时后面的覆盖前面的
+    Fuzzy(usize),
+}
+
+/// 被选中的行的输出顺序
+///
+/// Score 对所有被选中的行排序; 其他几种只决定搜索目录时文件的顺序, 每个文件内部仍然按行号输出
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// 按匹配的分数从好到差输出, 分数相同时保持搜索到的顺序. 模糊匹配的分数是编辑距离, 其他匹配方式都一样.
+    /// 所有结果都会先缓存起来, 搜索结束之后再输出, 不能和上下文一起使用
+    Score,
+    /// 按路径排序, 和不传 --sort 时的顺序一样, 只是明确地保证这一点
+    Path,
+    /// 按修改时间从旧到新, 读不到修改时间的文件排在最前面
+    Modified,
+    /// 按被选中的行数从多到少, 行数相同时按路径. 每个文件的结果都要先缓存起来
+    Matches,
+}
+
+/// 输出方式, 决定被选中的行 (-v 时是不匹配的行) 怎样输出
+///
+/// -v 只改变哪些行被选中, 可以和任何一种输出方式组合, 例如 `-vc` 统计不匹配的行数, 所以它不是这里的一种
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// 输出被选中的整行, 以及 -A / -B / -C 要求的上下文
+    #[default]
+    Lines,
+    /// 只输出行内匹配的部分, 每个匹配一行, -v 时什么都不输出
+    OnlyMatching,
+    /// 每个文件输出一次被选中的行数
+    Count,
+    /// 只输出有被选中的行的文件名
+    FilesWithMatches,
+    /// 只输出没有被选中的行的文件名
+    FilesWithoutMatch,
+    /// 什么都不输出, 只通过退出码表示是否有被选中的行
+    Quiet,
+    /// 每个事件输出一行 JSON, 包括被选中的行和上下文, 格式见 [`crate::json`]
+    Json,
+}
+
+/// 怎样搜索二进制输入, 即开头的 [`crate::walk::BINARY_SNIFF_LEN`] 个字节中有 NUL 的文件或标准输入
+///
+/// 目录中的二进制文件默认在遍历时就被跳过了 (见 --binary), 这里决定的是被搜索到的二进制输入怎样输出
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryFiles {
+    /// 照常搜索, 但不输出匹配的行, 有被选中的行时只输出一行 `Binary file X matches`.
+    /// -c / -l / -L / -q 和 --json 的输出不受影响
+    #[default]
+    Binary,
+    /// 和文本一样搜索并输出, 目录中的二进制文件也不再跳过
+    Text,
+    /// 当作没有任何匹配的文件, 不读取剩下的内容
+    WithoutMatch,
+}
+
+/// 什么时候输出颜色
+///
+/// Auto 由 [`crate::run`] 根据标准输出是否是终端决定. 直接使用 [`crate::printer::Printer`] 时,
+/// 它不知道输出的去向, 所以 Auto 和 Never 一样不输出颜色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+fn invalid(msg: String) -> Error {
+    Error::Args(msg)
+}
+
+impl Config {
+    // new 和 iter_new 共用同一套解析逻辑, new 只是多了一次 clone
+    pub fn new(args: &[String]) -> Result<Config, Error> {
+        Config::iter_new(args.iter().cloned())
+    }
+
+    // 优化后的 new 方法, 使用迭代器取代性能不好的 clone
+    pub fn iter_new(mut args: impl Iterator<Item = String>) -> Result<Config, Error> {
+        // 跳过第一个参数, 因为它是程序名
+        args.next();
+        // 优先级从低到高: 环境变量 IGNORE_CASE, 配置文件, 命令行
+        let mut cfg = Config {
+            ignore_case: ignore_case_from_env(),
+            ..Config::default()
+        };
+        // 子命令只能是第一个参数, 之后的 index 都是普通的位置参数
+        let mut args = args.peekable();
+        if args.next_if(|arg| arg == "index").is_some() {
+            cfg.build_index = true;
+        }
+        // 读配置文件之前就要知道有没有 --no-config, 所以先把命令行拆开, 读完配置文件再应用.
+        // 拆分时知道哪些参数是选项的值, `-e --no-config` 中的 --no-config 是模式
+        let args = split_args(args)?;
+        let no_config = args
+            .iter()
+            .any(|arg| matches!(arg, Arg::Option("no-config", _)));
+        match env::var_os("MINI_GREP_CONFIG") {
+            Some(path) if !no_config && !path.is_empty() => {
+                cfg.load_config_file(PathBuf::from(path))?
+            }
+            _ => {}
+        }
+        let mut positional = Vec::new();
+        cfg.apply(args, &mut positional)?;
+        if cfg.help || cfg.version || cfg.type_list {
+            return Ok(cfg);
+        }
+        let mut positional = positional.into_iter();
+        if cfg.build_index {
+            cfg.path = positional.next().unwrap_or_else(|| String::from("."));
+            if positional.next().is_some() {
+                Err(invalid("need at most 1 argument, index [dir]".to_string()))?
+            }
+            return Ok(cfg);
+        }
+        // 交互模式的 query 是在界面中输入的, 位置参数只有 path
+        if cfg.interactive {
+            cfg.path = positional.next().unwrap_or_else(|| String::from("."));
+            if positional.next().is_some() {
+                Err(invalid(
+                    "need at most 1 argument, --interactive [path]".to_string(),
+                ))?
+            }
+            if cfg.in_place {
+                Err(invalid(
+                    "option '--in-place' can't be used with --interactive".to_string(),
+                ))?
+            }
+            return Ok(cfg);
+        }
+        // 用 -e 或 -f 指定了模式时, 位置参数只剩下 path
+        let (query, path) = match (&cfg.patterns, positional.next(), positional.next()) {
+            (None, Some(query), path) => (query, path),
+            (Some(_), path, None) => (String::new(), path),
+            (Some(_), ..) => Err(invalid(
+                "need at most 1 argument, -e [pattern] [path]".to_string(),
+            ))?,
+            (None, None, _) if cfg.debug_config => (String::new(), None),
+            (None, None, _) => Err(invalid("need 1 or 2 arguments, [query] [path]".to_string()))?,
+        };
+        if positional.next().is_some() {
+            Err(invalid("need 1 or 2 arguments, [query] [path]".to_string()))?
+        }
+        cfg.query = query;
+        cfg.path = path.unwrap_or_else(|| crate::STDIN_PATH.to_string());
+        cfg.check_in_place()?;
+        if cfg.use_index && cfg.path == crate::STDIN_PATH {
+            Err(invalid(
+                "option '--index' requires a directory PATH".to_string(),
+            ))?
+        }
+        // -x 和 --in-place 都是按行处理的
+        for (conflict, name) in [(cfg.line_regexp, "-x"), (cfg.in_place, "--in-place")] {
+            if cfg.multiline && conflict {
+                Err(invalid(format!(
+                    "option '{}' can't be used with --multiline",
+                    name
+                )))?
+            }
+        }
+        let context = cfg.before_context > 0 || cfg.after_context > 0;
+        if cfg.sort == Some(SortBy::Score) && context {
+            Err(invalid(
+                "option '--sort=score' can't be used with context (-A/-B/-C)".to_string(),
+            ))?
+        }
+        Ok(cfg)
+    }
+
+    /// 实际要查找的所有模式: 传了 -e / -f 时是 patterns, 否则只有 query 一个
+    pub fn queries(&self) -> &[String] {
+        match &self.patterns {
+            Some(patterns) => patterns,
+            None => std::slice::from_ref(&self.query),
+        }
+    }
+
+    // 按顺序设置 args 中的选项, 位置参数按顺序放进 positional
+    fn apply(&mut self, args: Vec<Arg>, positional: &mut Vec<String>) -> Result<(), Error> {
+        for arg in args {
+            match arg {
+                Arg::Option(name, value) => self.set(name, value)?,
+                Arg::Positional(arg) => positional.push(arg),
+            }
+        }
+        Ok(())
+    }
+
+    // 配置文件每行一个参数, 只能是选项. 错误信息前面加上文件名, 方便找到是哪个配置出了问题
+    fn load_config_file(&mut self, path: PathBuf) -> Result<(), Error> {
+        let contents = fs::read_to_string(&path).map_err(|e| Error::file(&path, e))?;
+        let args = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string);
+        let in_file = |msg: String| invalid(format!("config file '{}': {}", path.display(), msg));
+        let mut positional = Vec::new();
+        split_args(args)
+            .and_then(|args| self.apply(args, &mut positional))
+            .map_err(|err| match err {
+                Error::Args(msg) => in_file(msg),
+                err => err,
+            })?;
+        if let Some(arg) = positional.first() {
+            Err(in_file(format!("'{}' is not an option", arg)))?
+        }
+        self.config_file = Some(path);
+        Ok(())
+    }
+
+    // 根据长参数名设置对应的字段
+    fn set(&mut self, name: &str, value: Option<String>) -> Result<(), Error> {
+        match name {
+            "ignore-case" => self.ignore_case = true,
+            "case-sensitive" => self.ignore_case = false,
+            "line-number" => self.line_number = true,
+            "invert-match" => self.invert = true,
+            "word-regexp" => self.word_regexp = true,
+            "line-regexp" => self.line_regexp = true,
+            "multiline" => self.multiline = true,
+            "count" => self.set_output(OutputMode::Count),
+            "files-with-matches" => self.set_output(OutputMode::FilesWithMatches),
+            "files-without-match" => self.set_output(OutputMode::FilesWithoutMatch),
+            "only-matching" => self.set_output(OutputMode::OnlyMatching),
+            "quiet" => self.set_output(OutputMode::Quiet),
+            "json" => self.set_output(OutputMode::Json),
+            "column" => self.column = true,
+            "replace" => self.replace = Some(Replacement::parse(&value.unwrap_or_default())?),
+            "in-place" => self.in_place = true,
+            "backup" => self.backup = value,
+            "color" => {
+                self.color = match value.as_deref() {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    _ => {
+                        return Err(invalid(format!(
+                        "invalid value '{}' for option '--color', expected auto, always or never",
+                        value.unwrap_or_default()
+                    )))
+                    }
+                }
+            }
+            "after-context" => self.after_context = parse_number(name, value)?,
+            "before-context" => self.before_context = parse_number(name, value)?,
+            "context" => {
+                self.after_context = parse_number(name, value)?;
+                self.before_context = self.after_context;
+            }
+            "regexp" => self.patterns.get_or_insert_with(Vec::new).extend(value),
+            "file" => {
+                let path = value.unwrap_or_default();
+                let contents = fs::read_to_string(&path).map_err(|e| Error::file(path, e))?;
+                // 和搜索时一样, 按 \n 或 \r\n 分行
+                let patterns = self.patterns.get_or_insert_with(Vec::new);
+                patterns.extend(contents.lines().map(str::to_string));
+            }
+            "regex" => self.mode = MatchMode::Regex,
+            "fuzzy" => self.mode = MatchMode::Fuzzy(parse_number(name, value)?),
+            "stats" => self.stats = true,
+            "sort" => {
+                self.sort = match value.as_deref() {
+                    Some("score") => Some(SortBy::Score),
+                    Some("path") => Some(SortBy::Path),
+                    Some("modified") => Some(SortBy::Modified),
+                    Some("matches") => Some(SortBy::Matches),
+                    _ => {
+                        return Err(invalid(format!(
+                            "invalid value '{}' for option '--sort', expected score, path, modified or matches",
+                            value.unwrap_or_default()
+                        )))
+                    }
+                }
+            }
+            "binary-files" => {
+                self.binary_files = match value.as_deref() {
+                    Some("binary") => BinaryFiles::Binary,
+                    Some("text") => BinaryFiles::Text,
+                    Some("without-match") => BinaryFiles::WithoutMatch,
+                    _ => {
+                        return Err(invalid(format!(
+                            "invalid value '{}' for option '--binary-files', expected binary, text or without-match",
+                            value.unwrap_or_default()
+                        )))
+                    }
+                }
+            }
+            "hidden" => self.hidden = true,
+            "binary" => self.binary = true,
+            "search-zip" => self.decompress = true,
+            "no-ignore" => self.no_ignore = true,
+            "include" => self.include.extend(value),
+            "exclude" => self.exclude.extend(value),
+            "type" => {
+                let name = value.unwrap_or_default();
+                if crate::walk::type_globs(&name).is_none() {
+                    return Err(invalid(format!(
+                        "unknown file type '{}', see --type-list",
+                        name
+                    )));
+                }
+                self.types.push(name);
+            }
+            "type-list" => self.type_list = true,
+            "threads" => self.jobs = parse_number(name, value)?,
+            "index" => self.use_index = true,
+            "help" => self.help = true,
+            "version" => self.version = true,
+            // 已经在读配置文件之前处理过了, 见 iter_new
+            "no-config" => {}
+            "debug-config" => self.debug_config = true,
+            "interactive" => self.interactive = true,
+            _ => unreachable!("option --{} is listed in OPTIONS but not handled", name),
+        }
+        Ok(())
+    }
+
+    // --in-place 和 --backup 需要和其他参数配合使用
+    fn check_in_place(&self) -> Result<(), Error> {
+        if self.backup.is_some() && !self.in_place {
+            Err(invalid("option '--backup' requires --in-place".to_string()))
+        } else if !self.in_place {
+            Ok(())
+        } else if self.replace.is_none() {
+            Err(invalid(
+                "option '--in-place' requires --replace".to_string(),
+            ))
+        } else if self.path == crate::STDIN_PATH {
+            Err(invalid(
+                "option '--in-place' requires a file or directory PATH".to_string(),
+            ))
+        } else if self.invert || self.decompress {
+            Err(invalid(format!(
+                "option '--in-place' can't be used with {}",
+                if self.invert {
+                    "--invert-match"
+                } else {
+                    "--search-zip"
+                }
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    // 同时传了多个输出方式时后面的覆盖前面的, 只有 -q 和 grep 一样总是优先
+    fn set_output(&mut self, output: OutputMode) {
+        if self.output != OutputMode::Quiet {
+            self.output = output;
+        }
+    }
+}
+
+fn parse_number(name: &str, value: Option<String>) -> Result<usize, Error> {
+    let value = value.unwrap_or_default();
+    value.parse().map_err(|_| {
+        invalid(format!(
+            "invalid number '{}' for option '--{}'",
+            value, name
+        ))
+    })
+}
+
+// 命令行或配置文件中的一项: 选项的长参数名和值, 或者位置参数
+#[derive(Debug, PartialEq)]
+enum Arg {
+    Option(&'static str, Option<String>),
+    Positional(String),
+}
+
+// 把参数拆成选项和位置参数, 不设置任何字段. 需要值的选项会带走后面的参数, `--` 之后的都是位置参数
+fn split_args(mut args: impl Iterator<Item = String>) -> Result<Vec<Arg>, Error> {
+    let mut split = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            split.extend(args.by_ref().map(Arg::Positional));
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let &(_, name, takes_value) = OPTIONS
+                .iter()
+                .find(|(_, long, _)| *long == name)
+                .ok_or_else(|| invalid(format!("unknown option '--{}', see --help", name)))?;
+            let value = match (takes_value, inline) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(next_value(&mut args, name)?),
+                (false, Some(_)) => {
+                    return Err(invalid(format!("option '--{}' takes no value", name)))
+                }
+                (false, None) => None,
+            };
+            split.push(Arg::Option(name, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // 短参数可以合并, 例如 -in; 需要值的参数后面剩下的部分就是值, 例如 -efoo
+            for (i, c) in arg.char_indices().skip(1) {
+                let &(_, name, takes_value) = OPTIONS
+                    .iter()
+                    .find(|(short, _, _)| *short == Some(c))
+                    .ok_or_else(|| invalid(format!("unknown option '-{}', see --help", c)))?;
+                if takes_value {
+                    let rest = &arg[i + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        next_value(&mut args, name)?
+                    } else {
+                        rest.to_string()
+                    };
+                    split.push(Arg::Option(name, Some(value)));
+                    break;
+                }
+                split.push(Arg::Option(name, None));
+            }
+        } else {
+            // 单独的 `-` 也是位置参数
+            split.push(Arg::Positional(arg));
+        }
+    }
+    Ok(split)
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, Error> {
+    args.next()
+        .ok_or_else(|| invalid(format!("option '--{}' requires a value", name)))
+}
+
+// env::var 返回一个 Result, 如果环境变量不存在, 则返回 Err
+fn ignore_case_from_env() -> bool {
+    match env::var("IGNORE_CASE") {
+        Ok(val) => val == "1" || val == "true" || val == "TRUE" || val == "True",
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_new() {
+        let args = vec![
+            String::from("mini_grep"),
+            String::from("searchstring"),
+            String::from("example-filename.txt"),
+        ];
+        let conf = Config::new(&args).unwrap();
+        assert_eq!(conf.query, "searchstring");
+        assert_eq!(conf.path, "example-filename.txt");
+    }
+
+    #[test]
+    #[should_panic(expected = "need 1 or 2 arguments, [query] [path]")]
+    fn test_new_with_invalid_args() {
+        let args = vec![String::from("mini_grep")];
+        let _conf = Config::new(&args).unwrap();
+    }
+
+    #[test]
+    fn test_new_without_path_reads_stdin() {
+        let conf = Config::new(&args(&["mini_grep", "searchstring"])).unwrap();
+        assert_eq!(conf.path, crate::STDIN_PATH);
+        let conf = Config::new(&args(&["mini_grep", "-e", "q"])).unwrap();
+        assert_eq!(conf.path, "-");
+    }
+
+    #[test]
+    fn test_new_with_regex() {
+        let args = vec![
+            String::from("mini_grep"),
+            String::from("--regex"),
+            String::from("^Rust"),
+            String::from("example-filename.txt"),
+        ];
+        let conf = Config::new(&args).unwrap();
+        assert_eq!(conf.mode, MatchMode::Regex);
+        assert_eq!(conf.query, "^Rust");
+        let conf = Config::iter_new(args.into_iter()).unwrap();
+        assert_eq!(conf.mode, MatchMode::Regex);
+        assert_eq!(conf.path, "example-filename.txt");
+    }
+
+    #[test]
+    fn test_new_with_walk_switches() {
+        let args = vec![
+            String::from("mini_grep"),
+            String::from("--hidden"),
+            String::from("searchstring"),
+            String::from("src"),
+            String::from("--binary"),
+        ];
+        let conf = Config::new(&args).unwrap();
+        assert!(conf.hidden && conf.binary);
+        assert_eq!(conf.path, "src");
+        let conf = Config::iter_new(args.into_iter()).unwrap();
+        assert!(conf.hidden && conf.binary);
+        assert_eq!(conf.query, "searchstring");
+        assert_eq!(conf.binary_files, BinaryFiles::Binary);
+    }
+
+    #[test]
+    fn test_short_and_long_flags() {
+        let conf = Config::new(&args(&["mini_grep", "-inv", "--count", "q", "p"])).unwrap();
+        assert!(conf.ignore_case && conf.line_number && conf.invert);
+        assert_eq!(conf.output, OutputMode::Count);
+        assert_eq!((conf.query.as_str(), conf.path.as_str()), ("q", "p"));
+        let conf = Config::new(&args(&["mini_grep", "-wx", "q"])).unwrap();
+        assert!(conf.word_regexp && conf.line_regexp);
+
+        let conf = Config::new(&args(&["mini_grep", "-n", "-e", "-q", "p"])).unwrap();
+        assert!(conf.line_number);
+        assert_eq!(conf.queries(), ["-q"]);
+        let conf = Config::new(&args(&["mini_grep", "-ce-x", "p"])).unwrap();
+        assert_eq!(conf.queries(), ["-x"]);
+        let conf = Config::new(&args(&[
+            "mini_grep",
+            "-C2",
+            "-A",
+            "3",
+            "--column",
+            "q",
+            "p",
+        ]))
+        .unwrap();
+        assert_eq!((conf.before_context, conf.after_context), (2, 3));
+        assert!(conf.column);
+        let conf = Config::new(&args(&["mini_grep", "-j4", "q", "p"])).unwrap();
+        assert_eq!(conf.jobs, 4);
+        assert_eq!(conf.color, ColorChoice::Auto);
+        let conf = Config::new(&args(&["mini_grep", "--color=always", "q", "p"])).unwrap();
+        assert_eq!(conf.color, ColorChoice::Always);
+        let conf = Config::new(&args(&["mini_grep", "--color", "never", "q"])).unwrap();
+        assert_eq!((conf.color, conf.query.as_str()), (ColorChoice::Never, "q"));
+        let conf = Config::new(&args(&[
+            "mini_grep",
+            "-t",
+            "rust",
+            "--include=*.md",
+            "--exclude",
+            "target",
+            "--no-ignore",
+            "q",
+        ]))
+        .unwrap();
+        assert_eq!(conf.types, ["rust"]);
+        assert_eq!(
+            (conf.include, conf.exclude),
+            (vec!["*.md".to_string()], vec!["target".to_string()])
+        );
+        assert!(conf.no_ignore);
+        assert!(
+            Config::new(&args(&["mini_grep", "--type-list"]))
+                .unwrap()
+                .type_list
+        );
+        let conf = Config::new(&args(&[
+            "mini_grep",
+            "--replace",
+            "<$0>",
+            "--in-place",
+            "--backup=.orig",
+            "q",
+            "p",
+        ]))
+        .unwrap();
+        assert_eq!(conf.replace, Some(Replacement::parse("<$0>").unwrap()));
+        assert!(conf.in_place);
+        assert_eq!(conf.backup.as_deref(), Some(".orig"));
+        // --regex 和 --fuzzy 后面的覆盖前面的
+        let conf = Config::new(&args(&[
+            "mini_grep",
+            "--regex",
+            "--fuzzy",
+            "2",
+            "--sort=score",
+            "q",
+        ]))
+        .unwrap();
+        assert_eq!(
+            (conf.mode, conf.sort),
+            (MatchMode::Fuzzy(2), Some(SortBy::Score))
+        );
+        let conf = Config::new(&args(&["mini_grep", "--fuzzy=1", "--regex", "q"])).unwrap();
+        assert_eq!(conf.mode, MatchMode::Regex);
+        let conf = Config::new(&args(&["mini_grep", "--interactive", "-i"])).unwrap();
+        assert!(conf.interactive && conf.ignore_case);
+        assert_eq!((conf.query.as_str(), conf.path.as_str()), ("", "."));
+        // 按文件排序时可以有上下文
+        let conf = Config::new(&args(&[
+            "mini_grep",
+            "--sort=matches",
+            "--stats",
+            "-C1",
+            "q",
+        ]))
+        .unwrap();
+        assert_eq!(conf.sort, Some(SortBy::Matches));
+        assert!(conf.stats);
+        let conf = Config::new(&args(&[
+            "mini_grep",
+            "--binary-files=without-match",
+            "-U",
+            "q",
+        ]))
+        .unwrap();
+        assert_eq!(conf.binary_files, BinaryFiles::WithoutMatch);
+        assert!(conf.multiline);
+        let conf = Config::new(&args(&["mini_grep", "index", "--hidden", "src"])).unwrap();
+        assert!(conf.build_index && conf.hidden);
+        assert_eq!((conf.query.as_str(), conf.path.as_str()), ("", "src"));
+        assert_eq!(
+            Config::new(&args(&["mini_grep", "index"])).unwrap().path,
+            "."
+        );
+        // 只有第一个参数是子命令
+        let conf = Config::new(&args(&["mini_grep", "--index", "index", "src"])).unwrap();
+        assert!(conf.use_index && !conf.build_index);
+        assert_eq!((conf.query.as_str(), conf.path.as_str()), ("index", "src"));
+        let conf = Config::new(&args(&["mini_grep", "--regexp=--", "p"])).unwrap();
+        assert_eq!(conf.queries(), ["--"]);
+
+        // -- 之后的参数都是位置参数
+        let conf = Config::new(&args(&["mini_grep", "--", "-v", "-"])).unwrap();
+        assert!(!conf.invert);
+        assert_eq!((conf.query.as_str(), conf.path.as_str()), ("-v", "-"));
+    }
+
+    #[test]
+    fn test_output_modes() {
+        let output = |list: &[&str]| Config::new(&args(list)).unwrap().output;
+        assert_eq!(output(&["mini_grep", "q"]), OutputMode::Lines);
+        assert_eq!(output(&["mini_grep", "-o", "q"]), OutputMode::OnlyMatching);
+        assert_eq!(
+            output(&["mini_grep", "-l", "q"]),
+            OutputMode::FilesWithMatches
+        );
+        assert_eq!(
+            output(&["mini_grep", "--files-without-match", "q"]),
+            OutputMode::FilesWithoutMatch
+        );
+        assert_eq!(output(&["mini_grep", "-lc", "q"]), OutputMode::Count);
+        assert_eq!(output(&["mini_grep", "--json", "q"]), OutputMode::Json);
+        assert_eq!(output(&["mini_grep", "-ql", "q"]), OutputMode::Quiet);
+        assert_eq!(output(&["mini_grep", "-cq", "q"]), OutputMode::Quiet);
+    }
+
+    #[test]
+    fn test_multiple_patterns() {
+        let conf = Config::new(&args(&["mini_grep", "q", "p"])).unwrap();
+        assert_eq!(conf.queries(), ["q"]);
+        let conf = Config::new(&args(&["mini_grep", "-e", "a", "-eb", "--regexp=c", "p"])).unwrap();
+        assert_eq!(conf.queries(), ["a", "b", "c"]);
+        assert_eq!(conf.path, "p");
+
+        let file = env::temp_dir().join(format!("mini_grep_patterns_{}", std::process::id()));
+        fs::write(&file, "one\r\ntwo\n\nthree").unwrap();
+        let file = file.to_str().unwrap();
+        let conf = Config::new(&args(&["mini_grep", "-e", "zero", "-f", file])).unwrap();
+        assert_eq!(conf.queries(), ["zero", "one", "two", "", "three"]);
+        assert_eq!(conf.path, "-");
+        // 空的模式文件不匹配任何行, 但仍然算指定了模式
+        fs::write(file, "").unwrap();
+        let conf = Config::new(&args(&["mini_grep", "-f", file, "p"])).unwrap();
+        assert!(conf.queries().is_empty());
+        assert_eq!(conf.path, "p");
+        fs::remove_file(file).unwrap();
+        assert!(Config::new(&args(&["mini_grep", "-f", file, "p"])).is_err());
+    }
+
+    #[test]
+    fn test_config_file() {
+        // 不设置 MINI_GREP_CONFIG, 环境变量是整个进程共享的, 会影响同时运行的其他测试
+        let file = env::temp_dir().join(format!("mini_grep_config_{}", std::process::id()));
+        fs::write(&file, "# defaults\n--ignore-case\n\n  --context=2  \n-j4\n").unwrap();
+        let mut conf = Config::default();
+        conf.load_config_file(file.clone()).unwrap();
+        assert!(conf.ignore_case);
+        assert_eq!((conf.before_context, conf.jobs), (2, 4));
+        assert_eq!(conf.config_file.as_ref(), Some(&file));
+        // 命令行在配置文件之后解析, 所以优先
+        let mut positional = Vec::new();
+        let split = split_args(args(&["-s", "-A", "0", "q"]).into_iter()).unwrap();
+        conf.apply(split, &mut positional).unwrap();
+        assert!(!conf.ignore_case);
+        assert_eq!((conf.before_context, conf.after_context), (2, 0));
+        assert_eq!(positional, ["q"]);
+
+        fs::write(&file, "--hidden\nquery\n").unwrap();
+        let err = Config::default()
+            .load_config_file(file.clone())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("config file '{}': 'query' is not an option", file.display())
+        );
+        fs::remove_file(&file).unwrap();
+        assert!(matches!(
+            Config::default().load_config_file(file),
+            Err(Error::File { .. })
+        ));
+        let conf = Config::new(&args(&["mini_grep", "--no-config", "--debug-config"])).unwrap();
+        assert!(conf.debug_config && conf.config_file.is_none());
+        // 选项的值不是 --no-config 选项
+        let split = split_args(args(&["-e", "--no-config", "--", "--no-config"]).into_iter());
+        assert_eq!(
+            split.unwrap(),
+            [
+                Arg::Option("regexp", Some(String::from("--no-config"))),
+                Arg::Positional(String::from("--no-config")),
+            ]
+        );
+        let conf = Config::new(&args(&["mini_grep", "-e", "--no-config", "file"])).unwrap();
+        assert_eq!(conf.queries(), ["--no-config"]);
+        assert_eq!(conf.path, "file");
+    }
+
+    #[test]
+    fn test_help_version_and_errors() {
+        assert!(Config::new(&args(&["mini_grep", "--help"])).unwrap().help);
+        assert!(Config::new(&args(&["mini_grep", "-V"])).unwrap().version);
+        let err = |list: &[&str]| Config::new(&args(list)).unwrap_err().to_string();
+        assert_eq!(
+            err(&["mini_grep", "--colour", "q", "p"]),
+            "unknown option '--colour', see --help"
+        );
+        assert_eq!(
+            err(&["mini_grep", "-Y", "q", "p"]),
+            "unknown option '-Y', see --help"
+        );
+        assert_eq!(
+            err(&["mini_grep", "p", "-e"]),
+            "option '--regexp' requires a value"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--color=yes", "q", "p"]),
+            "invalid value 'yes' for option '--color', expected auto, always or never"
+        );
+        assert_eq!(
+            err(&["mini_grep", "index", "a", "b"]),
+            "need at most 1 argument, index [dir]"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--index", "q"]),
+            "option '--index' requires a directory PATH"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--binary-files=skip", "q"]),
+            "invalid value 'skip' for option '--binary-files', expected binary, text or without-match"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--sort=name", "q", "p"]),
+            "invalid value 'name' for option '--sort', expected score, path, modified or matches"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--interactive", "q", "p"]),
+            "need at most 1 argument, --interactive [path]"
+        );
+        assert_eq!(
+            err(&["mini_grep", "-Ux", "q", "p"]),
+            "option '-x' can't be used with --multiline"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--sort=score", "-C1", "q", "p"]),
+            "option '--sort=score' can't be used with context (-A/-B/-C)"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--fuzzy", "-1", "q", "p"]),
+            "invalid number '-1' for option '--fuzzy'"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--in-place", "q", "p"]),
+            "option '--in-place' requires --replace"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--replace=x", "--in-place", "q"]),
+            "option '--in-place' requires a file or directory PATH"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--backup", ".bak", "q", "p"]),
+            "option '--backup' requires --in-place"
+        );
+        assert_eq!(
+            err(&["mini_grep", "-t", "cobol", "q"]),
+            "unknown file type 'cobol', see --type-list"
+        );
+        assert_eq!(
+            err(&["mini_grep", "--count=1", "q", "p"]),
+            "option '--count' takes no value"
+        );
+        assert_eq!(
+            err(&["mini_grep", "-A", "x", "q", "p"]),
+            "invalid number 'x' for option '--after-context'"
+        );
+        assert_eq!(
+            err(&["mini_grep", "-e", "q", "a", "b"]),
+            "need at most 1 argument, -e [pattern] [path]"
+        );
+    }
+}
@@ -0,0 +1,56 @@
+//! mini_grep 的错误类型
+//!
+//! 按照出错的原因区分, main 根据它决定错误信息的格式. 无论哪种错误, 退出码都是 2,
+//! 和 grep 一样: 0 表示有被选中的行, 1 表示没有, 2 表示出错.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    /// 命令行参数不合法
+    Args(String),
+    /// 模式不合法, 例如正则表达式的语法错误
+    Pattern(String),
+    /// 打开或读取某个文件 (包括目录和 -f 指定的模式文件) 失败
+    File { path: PathBuf, source: io::Error },
+    /// 其他 I/O 错误, 例如写标准输出失败
+    Io(io::Error),
+}
+
+impl Error {
+    pub(crate) fn file(path: impl Into<PathBuf>, source: io::Error) -> Error {
+        Error::File {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Args(msg) | Error::Pattern(msg) => write!(f, "{}", msg),
+            Error::File { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::File { source, .. } => Some(source),
+            Error::Io(err) => Some(err),
+            Error::Args(_) | Error::Pattern(_) => None,
+        }
+    }
+}
+
+// 没有对应文件的 I/O 错误, 例如 Printer 写输出失败, 可以直接用 ? 转换
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
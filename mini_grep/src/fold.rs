@@ -0,0 +1,225 @@
+//! 忽略大小写的匹配
+//!
+//! 比较前把两边的字符都做 Unicode 大小写折叠 (case folding), 而不是调用 `to_lowercase()` 生成新的字符串.
+//! 折叠是逐个字符进行的, 所以搜索时不需要为每一行分配内存.
+//!
+//! 折叠使用完整映射, 一个字符可能折叠成多个字符, 例如 `ß` 和 `ẞ` 都折叠成 `ss`, 所以 `STRASSE` 可以匹配
+//! `straße`. `İ` (带点的大写 I) 折叠成 `i` 加上组合用的点, 没有点的 `ı` 保持不变,
+//! 这和 Unicode CaseFolding.txt 的默认 (非土耳其语) 规则一致.
+
+// 一个字符折叠之后最多有几个字符, test_fold_len 会检查所有的字符
+const MAX_FOLD: usize = 3;
+
+// 折叠结果以 ASCII 字符开头的非 ASCII 字符, 按码点排序, test_folds_to_ascii 会检查这张表是否完整.
+// 查询字符串以 ASCII 字符开头时, 其他非 ASCII 字符都不可能是匹配的开头, 不需要折叠
+const FOLDS_TO_ASCII: &[char] = &[
+    'ß', 'İ', 'ſ', 'ǰ', 'ẖ', 'ẗ', 'ẘ', 'ẙ', 'ẚ', 'ẞ', '\u{212a}', 'ﬀ', 'ﬁ', 'ﬂ', 'ﬃ', 'ﬄ', 'ﬅ', 'ﬆ',
+];
+
+/// 一个字符折叠之后的字符序列, 保存在栈上的定长数组里, 不需要分配内存
+#[derive(Debug, Clone)]
+pub struct Fold {
+    chars: [char; MAX_FOLD],
+    len: usize,
+    pos: usize,
+}
+
+impl Iterator for Fold {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars[..self.len].get(self.pos).copied()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.len - self.pos;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for Fold {}
+
+// 不做折叠, 只包含字符本身, 方便区分大小写和忽略大小写时使用同样的代码
+impl From<char> for Fold {
+    fn from(c: char) -> Fold {
+        Fold {
+            chars: [c; MAX_FOLD],
+            len: 1,
+            pos: 0,
+        }
+    }
+}
+
+/// 返回 c 折叠之后的字符序列
+///
+/// 先转小写再转大写再转小写, 可以覆盖 `ẞ -> ß -> SS -> ss`, `ſ -> S -> s`, `ς -> Σ -> σ` 这类映射.
+/// 只有 `ı` 是例外: 它的大写是 `I`, 但按照 Unicode 的规则它不和 `i` 等价.
+pub fn fold(c: char) -> Fold {
+    let mut folded = Fold {
+        chars: [c.to_ascii_lowercase(); MAX_FOLD],
+        len: 1,
+        pos: 0,
+    };
+    if c.is_ascii() || c == 'ı' {
+        return folded;
+    }
+    folded.len = 0;
+    let chars = c
+        .to_lowercase()
+        .flat_map(char::to_uppercase)
+        .flat_map(char::to_lowercase);
+    for f in chars {
+        folded.chars[folded.len] = f;
+        folded.len += 1;
+    }
+    folded
+}
+
+/// 判断两个字符在忽略大小写时是否相等
+pub fn chars_eq(a: char, b: char) -> bool {
+    a == b || fold(a).eq(fold(b))
+}
+
+/// 忽略大小写的子串查找, query 只在创建时折叠一次
+///
+/// # Example
+/// ```
+/// use pandastd_mini_grep::fold::CaseInsensitive;
+///
+/// let finder = CaseInsensitive::new("STRASSE");
+/// assert_eq!(finder.find("die Straße"), Some((4, 11)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CaseInsensitive {
+    folded: Vec<char>,
+}
+
+impl CaseInsensitive {
+    pub fn new(query: &str) -> CaseInsensitive {
+        CaseInsensitive {
+            folded: query.chars().flat_map(fold).collect(),
+        }
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// 返回第一个匹配在 haystack 中的字节区间
+    ///
+    /// 匹配必须从一个字符开始, 在一个字符结束, 不会只匹配某个字符折叠结果的一部分,
+    /// 例如 `s` 不会匹配 `ß`
+    pub fn find(&self, haystack: &str) -> Option<(usize, usize)> {
+        let Some(&first) = self.folded.first() else {
+            return Some((0, 0));
+        };
+        // 大部分位置第一个字符就对不上, 所以先按字节扫描找候选位置:
+        // ASCII 字符直接比较小写形式, 只有遇到非 ASCII 字符的首字节时才需要解码
+        let bytes = haystack.as_bytes();
+        let mut start = 0;
+        while start < bytes.len() {
+            let b = bytes[start];
+            let (candidate, len) = if b.is_ascii() {
+                (b.to_ascii_lowercase() as u32 == first as u32, 1)
+            } else {
+                let c = haystack[start..].chars().next().unwrap();
+                let candidate = if first.is_ascii() {
+                    FOLDS_TO_ASCII.binary_search(&c).is_ok() && fold(c).next() == Some(first)
+                } else {
+                    fold(c).next() == Some(first)
+                };
+                (candidate, c.len_utf8())
+            };
+            if candidate {
+                if let Some(end) = self.match_at(haystack, start) {
+                    return Some((start, end));
+                }
+            }
+            start += len;
+        }
+        None
+    }
+
+    // 从 start 开始逐个字符折叠并比较, 匹配成功时返回结束位置
+    fn match_at(&self, haystack: &str, start: usize) -> Option<usize> {
+        let mut want = self.folded.iter();
+        for (offset, c) in haystack[start..].char_indices() {
+            if c.is_ascii() {
+                if want.next() != Some(&c.to_ascii_lowercase()) {
+                    return None;
+                }
+            } else {
+                for f in fold(c) {
+                    // query 已经用完, 但这个字符的折叠结果还没比较完, 说明只匹配了一部分
+                    if want.next() != Some(&f) {
+                        return None;
+                    }
+                }
+            }
+            if want.len() == 0 {
+                return Some(start + offset + c.len_utf8());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold() {
+        let folded = |s: &str| s.chars().flat_map(fold).collect::<String>();
+        assert_eq!(folded("Rust"), "rust");
+        assert_eq!(folded("ßẞ"), "ssss");
+        assert_eq!(folded("ſΣς"), "sσσ");
+        assert_eq!(folded("İ"), "i\u{307}");
+        assert_eq!(folded("ı"), "ı");
+        assert!(chars_eq('K', '\u{212a}'));
+        assert!(!chars_eq('ı', 'I'));
+    }
+
+    #[test]
+    fn test_fold_len() {
+        let longest = (0..=char::MAX as u32)
+            .filter_map(char::from_u32)
+            .map(|c| {
+                c.to_lowercase()
+                    .flat_map(char::to_uppercase)
+                    .flat_map(char::to_lowercase)
+                    .count()
+            })
+            .max();
+        assert_eq!(longest, Some(MAX_FOLD));
+    }
+
+    #[test]
+    fn test_folds_to_ascii() {
+        let expected: Vec<char> = (0x80..=char::MAX as u32)
+            .filter_map(char::from_u32)
+            .filter(|&c| fold(c).next().is_some_and(|f| f.is_ascii()))
+            .collect();
+        assert_eq!(FOLDS_TO_ASCII, expected);
+    }
+
+    #[test]
+    fn test_find() {
+        let find = |query: &str, haystack: &str| CaseInsensitive::new(query).find(haystack);
+        assert_eq!(find("duct", "safe, fast, PRODUCTIVE."), Some((15, 19)));
+        assert_eq!(find("STRASSE", "straße"), Some((0, 7)));
+        assert_eq!(find("straße", "STRASSE"), Some((0, 7)));
+        assert_eq!(find("ΣΙΣΥΦΟΣ", "σισυφος"), Some((0, 14)));
+        assert_eq!(find("x", "İİX"), Some((4, 5)));
+        // 不能只匹配 ß 折叠结果的一半
+        assert_eq!(find("s", "ß"), None);
+        assert_eq!(find("sa", "ßa"), None);
+        assert_eq!(find("i", "ı"), None);
+        assert_eq!(find("fish", "a ﬁsh"), Some((2, 7)));
+        assert_eq!(find("kelvin", "\u{212a}elvin"), Some((0, 8)));
+        assert_eq!(find("", "abc"), Some((0, 0)));
+        assert_eq!(find("abc", "ab"), None);
+    }
+}
@@ -0,0 +1,306 @@
+//! 模糊匹配: 查找和模式的编辑距离 (Levenshtein 距离) 不超过 k 的子串
+//!
+//! 插入, 删除和替换一个字符的代价都是 1, 例如 `--fuzzy 1 receive` 可以匹配 receve, 而 recieve 需要 `--fuzzy 2`.
+//! 模式不超过 64 个字符时使用 Myers 的位并行算法 (1999), 把动态规划表的一整列压缩进一个 u64,
+//! 每个字符只需要常数次位运算; 更长的模式退化为逐列计算的动态规划 (Sellers 算法).
+//!
+//! 找到编辑距离最小的结束位置之后, 再在它前面最多 `m + k` 个字符的窗口里反向计算一次动态规划,
+//! 得到匹配的开始位置, 所以匹配的区间可以像普通匹配一样高亮.
+
+use crate::fold;
+
+// Myers 算法中一列的位数
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// 编译好的模糊匹配模式
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    pattern: Vec<char>,
+    k: usize,
+    ignore_case: bool,
+    // 每个字符在模式中出现的位置, 第 i 位为 1 表示 pattern[i] 是这个字符. 只在模式不超过 64 个字符时使用
+    ascii: [u64; 128],
+    other: Vec<(char, u64)>,
+}
+
+impl Fuzzy {
+    /// k 是允许的最大编辑距离, ignore_case 为 true 时按大小写折叠后比较
+    pub fn new(pattern: &str, k: usize, ignore_case: bool) -> Fuzzy {
+        let mut fuzzy = Fuzzy {
+            pattern: Vec::new(),
+            k,
+            ignore_case,
+            ascii: [0; 128],
+            other: Vec::new(),
+        };
+        fuzzy.pattern = pattern.chars().map(|c| fuzzy.key(c)).collect();
+        if fuzzy.pattern.len() <= WORD_BITS {
+            for (i, &c) in fuzzy.pattern.iter().enumerate() {
+                let bit = 1u64 << i;
+                if c.is_ascii() {
+                    fuzzy.ascii[c as usize] |= bit;
+                } else if let Some((_, mask)) = fuzzy.other.iter_mut().find(|(o, _)| *o == c) {
+                    *mask |= bit;
+                } else {
+                    fuzzy.other.push((c, bit));
+                }
+            }
+        }
+        fuzzy
+    }
+
+    /// 允许的最大编辑距离
+    pub fn max_distance(&self) -> usize {
+        self.k
+    }
+
+    /// 在 text 中查找编辑距离最小的子串, 返回 `(start, end, distance)`, 最小距离超过 k 时返回 None.
+    /// 距离相同时取最靠左的, 但是会包含紧接着的距离相同的结束位置, 例如 `--fuzzy 2 receive` 在 "recieve" 中匹配整个单词
+    pub fn find(&self, text: &str) -> Option<(usize, usize, usize)> {
+        let (end, distance) = if self.pattern.len() <= WORD_BITS {
+            self.myers(text)?
+        } else {
+            self.sellers(text)?
+        };
+        Some((self.start(text, end, distance), end, distance))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// 模式和整个 text 的编辑距离, 用于 -x 和给匹配打分
+    pub fn distance(&self, text: &str) -> usize {
+        let text: Vec<char> = text.chars().map(|c| self.key(c)).collect();
+        *self.edit_row(self.pattern.iter(), &text).last().unwrap()
+    }
+
+    // 忽略大小写时, 折叠结果只有一个字符的按折叠后的字符比较, 其他字符 (例如 ß) 保持原样
+    fn key(&self, c: char) -> char {
+        if !self.ignore_case {
+            return c;
+        }
+        let mut folded = fold::fold(c);
+        match (folded.len(), folded.next()) {
+            (1, Some(f)) => f,
+            _ => c,
+        }
+    }
+
+    fn peq(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            self.ascii[c as usize]
+        } else {
+            self.other
+                .iter()
+                .find(|(o, _)| *o == c)
+                .map_or(0, |&(_, mask)| mask)
+        }
+    }
+
+    // 返回编辑距离最小的结束位置 (字节偏移) 和距离
+    fn myers(&self, text: &str) -> Option<(usize, usize)> {
+        let m = self.pattern.len();
+        // 空子串和模式的距离是 m
+        let mut best = Best::new(m, self.k);
+        if m == 0 {
+            return best.result();
+        }
+        let high = 1u64 << (m - 1);
+        // pv / mv: 这一列中相邻两行的差是 +1 / -1 的位置
+        let (mut pv, mut mv) = (!0u64, 0u64);
+        let mut score = m;
+        for (n, (i, c)) in text.char_indices().enumerate() {
+            let eq = self.peq(self.key(c));
+            let xv = eq | mv;
+            let xh = ((eq & pv).wrapping_add(pv) ^ pv) | eq;
+            let mut ph = mv | !(xh | pv);
+            let mut mh = pv & xh;
+            if ph & high != 0 {
+                score += 1;
+            } else if mh & high != 0 {
+                score -= 1;
+            }
+            // 匹配可以从任意位置开始, 所以第 0 行始终是 0, 移位时不补 1
+            ph <<= 1;
+            mh <<= 1;
+            pv = mh | !(xv | ph);
+            mv = ph & xv;
+            if best.update(n, i + c.len_utf8(), score) {
+                break;
+            }
+        }
+        best.result()
+    }
+
+    // 和 myers 的结果相同, 用于超过 64 个字符的模式. column[i] 是模式前 i 个字符在当前位置结束的最小距离
+    fn sellers(&self, text: &str) -> Option<(usize, usize)> {
+        let m = self.pattern.len();
+        let mut best = Best::new(m, self.k);
+        let mut column: Vec<usize> = (0..=m).collect();
+        for (n, (i, c)) in text.char_indices().enumerate() {
+            let key = self.key(c);
+            let mut diagonal = column[0];
+            for row in 1..=m {
+                let cost = usize::from(self.pattern[row - 1] != key);
+                let value = (column[row] + 1)
+                    .min(column[row - 1] + 1)
+                    .min(diagonal + cost);
+                diagonal = column[row];
+                column[row] = value;
+            }
+            let score = column[m];
+            if best.update(n, i + c.len_utf8(), score) {
+                break;
+            }
+        }
+        best.result()
+    }
+
+    // 从 end 往前最多 m + distance 个字符内, 找到距离等于 distance 的最短匹配的开始位置
+    fn start(&self, text: &str, end: usize, distance: usize) -> usize {
+        let window: Vec<(usize, char)> = text[..end]
+            .char_indices()
+            .rev()
+            .take(self.pattern.len() + distance)
+            .map(|(i, c)| (i, self.key(c)))
+            .collect();
+        let chars: Vec<char> = window.iter().map(|&(_, c)| c).collect();
+        let row = self.edit_row(self.pattern.iter().rev(), &chars);
+        match row.iter().position(|&d| d == distance) {
+            Some(0) | None => end,
+            Some(j) => window[j - 1].0,
+        }
+    }
+
+    // 模式和 text 开头都对齐的动态规划, 返回最后一行: row[j] 是整个模式和 text 前 j 个字符的编辑距离
+    fn edit_row<'a>(&self, pattern: impl Iterator<Item = &'a char>, text: &[char]) -> Vec<usize> {
+        let mut row: Vec<usize> = (0..=text.len()).collect();
+        for (i, &p) in pattern.enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for j in 1..=text.len() {
+                let cost = usize::from(p != text[j - 1]);
+                let value = (row[j] + 1).min(row[j - 1] + 1).min(diagonal + cost);
+                diagonal = row[j];
+                row[j] = value;
+            }
+        }
+        row
+    }
+}
+
+// 记录编辑距离最小的结束位置. 距离相同时取结束位置最靠左的, 但是在它之后 distance 个字符以内
+// 同样距离的结束位置会替换它 (可以连续替换), 否则 `--fuzzy 2 receive` 在 "recieve" 中只会匹配到 "recie"
+struct Best {
+    k: usize,
+    // (结束位置的字节偏移, 距离, 可以被替换的最后一个字符下标)
+    best: Option<(usize, usize, usize)>,
+}
+
+impl Best {
+    // 空子串和长度为 m 的模式的距离是 m
+    fn new(m: usize, k: usize) -> Best {
+        Best {
+            k,
+            best: (m <= k).then_some((0, m, m)),
+        }
+    }
+
+    // n 是当前字符的下标, end 是它之后的字节偏移. 返回 true 表示已经找到了距离为 0 的匹配, 不用再找了
+    fn update(&mut self, n: usize, end: usize, score: usize) -> bool {
+        let replace = match self.best {
+            _ if score > self.k => false,
+            None => true,
+            Some((_, distance, until)) => score < distance || (score == distance && n <= until),
+        };
+        if replace {
+            self.best = Some((end, score, n + score));
+        }
+        self.best.is_some_and(|(_, distance, _)| distance == 0)
+    }
+
+    fn result(&self) -> Option<(usize, usize)> {
+        self.best.map(|(end, distance, _)| (end, distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(pattern: &str, k: usize, text: &'a str) -> Option<(&'a str, usize)> {
+        let (start, end, distance) = Fuzzy::new(pattern, k, false).find(text)?;
+        Some((&text[start..end], distance))
+    }
+
+    #[test]
+    fn test_find() {
+        // 交换两个相邻字符需要两次编辑
+        assert_eq!(find("receive", 1, "we recieve it"), None);
+        assert_eq!(find("receive", 2, "we recieve it"), Some(("recieve", 2)));
+        assert_eq!(find("receive", 1, "we receve it"), Some(("receve", 1)));
+        assert_eq!(
+            find("productive", 1, "safe, fast, prodctive."),
+            Some(("prodctive", 1))
+        );
+        assert_eq!(find("fast", 0, "safe, fast"), Some(("fast", 0)));
+        assert_eq!(find("fast", 0, "safe, fsat"), None);
+        // 距离相同时取最靠左的, 找到距离为 0 的匹配时直接结束
+        assert_eq!(find("abc", 1, "xabx abd"), Some(("abx", 1)));
+        assert_eq!(find("abc", 1, "xabx abc"), Some(("abc", 0)));
+        assert_eq!(find("abc", 1, "abc xabx"), Some(("abc", 0)));
+        assert_eq!(find("", 0, "anything"), Some(("", 0)));
+        assert_eq!(find("ab", 2, ""), Some(("", 2)));
+        // ß 和 ss 不是同一个字符, 需要一次替换和一次插入
+        assert_eq!(find("Straße", 1, "die Strasse"), None);
+        assert_eq!(find("Straße", 2, "die Strasse"), Some(("Strasse", 2)));
+    }
+
+    #[test]
+    fn test_ignore_case_and_distance() {
+        let fuzzy = Fuzzy::new("Receive", 1, true);
+        assert!(fuzzy.is_match("RECEVE"));
+        assert_eq!(fuzzy.distance("recieve"), 2);
+        assert_eq!(fuzzy.distance("receive"), 0);
+        assert_eq!(fuzzy.max_distance(), 1);
+    }
+
+    #[test]
+    fn test_myers_matches_dynamic_programming() {
+        // 用一个简单的伪随机数生成器构造测试数据, 比较两种算法的结果
+        let mut seed = 0x2545_f491_u32;
+        let mut next = |n: u32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed % n
+        };
+        for _ in 0..300 {
+            let pattern: String = (0..1 + next(8))
+                .map(|_| (b'a' + next(3) as u8) as char)
+                .collect();
+            let text: String = (0..next(30))
+                .map(|_| (b'a' + next(3) as u8) as char)
+                .collect();
+            let k = next(3) as usize;
+            let fuzzy = Fuzzy::new(&pattern, k, false);
+            assert_eq!(
+                fuzzy.myers(&text),
+                fuzzy.sellers(&text),
+                "{} {} {}",
+                pattern,
+                k,
+                text
+            );
+            if let Some((start, end, distance)) = fuzzy.find(&text) {
+                assert_eq!(fuzzy.distance(&text[start..end]), distance);
+            }
+        }
+        // 超过 64 个字符的模式
+        let long = "ab".repeat(40);
+        let text = format!("xx{}yy", long.replacen("ab", "b", 1));
+        let fuzzy = Fuzzy::new(&long, 1, false);
+        assert_eq!(fuzzy.find(&text), Some((2, 81, 1)));
+    }
+}
@@ -0,0 +1,174 @@
+//! 通配符 (glob) 匹配, 用于 `--include` / `--exclude` 和忽略文件
+//!
+//! 支持的语法, 和 gitignore 使用的一样:
+//! - `*` 匹配除 `/` 以外的任意多个字符, `?` 匹配除 `/` 以外的一个字符
+//! - `[abc]`, `[a-z]`, `[!0-9]` (也可以写成 `[^0-9]`), 同样不会匹配 `/`
+//! - `**` 作为完整的一段时跨越目录: `**/foo` 匹配任意深度的 foo, `foo/**` 匹配 foo 下面的所有内容,
+//!   `a/**/b` 匹配 `a/b`, `a/x/b`, `a/x/y/b`; 其他位置的 `**` 和 `*` 相同
+//! - `\` 转义下一个字符, 例如 `\*` 匹配 `*` 本身
+//!
+//! 实现上把 glob 翻译成锚定在开头和结尾的正则表达式, 交给 [`crate::regex`] 匹配.
+
+use crate::regex::Regex;
+use crate::Error;
+
+/// 编译好的 glob, 匹配的是用 `/` 分隔的相对路径
+#[derive(Debug, Clone)]
+pub struct Glob {
+    glob: String,
+    re: Regex,
+}
+
+// 匹配任意字符, 包括 `/` 和换行
+const ANY: &str = "(?:[^/]|/)*";
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Glob, Error> {
+        let re = Regex::new(&translate(glob))
+            .map_err(|err| Error::Pattern(format!("invalid glob '{}': {}", glob, err)))?;
+        Ok(Glob {
+            glob: glob.to_string(),
+            re,
+        })
+    }
+
+    /// 整个 path 都和 glob 匹配时返回 true
+    pub fn is_match(&self, path: &str) -> bool {
+        self.re.is_match(path)
+    }
+
+    /// 原始的 glob 字符串
+    pub fn as_str(&self) -> &str {
+        &self.glob
+    }
+}
+
+fn translate(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut re = String::from("^");
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                let start = i;
+                while chars.get(i + 1) == Some(&'*') {
+                    i += 1;
+                }
+                let segment_start = start == 0 || chars[start - 1] == '/';
+                let double = i > start;
+                match chars.get(i + 1) {
+                    // `**/` 匹配零个或多个目录
+                    Some('/') if double && segment_start => {
+                        re.push_str("(?:[^/]*/)*");
+                        i += 1;
+                    }
+                    None if double && segment_start => re.push_str(ANY),
+                    _ => re.push_str("[^/]*"),
+                }
+            }
+            '?' => re.push_str("[^/]"),
+            '[' => match class(&chars[i + 1..]) {
+                Some((class, len)) => {
+                    re.push_str(&class);
+                    i += len;
+                }
+                // 没有对应的 `]` 时 `[` 是普通字符
+                None => re.push_str("\\["),
+            },
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                push_literal(&mut re, chars[i]);
+            }
+            c => push_literal(&mut re, c),
+        }
+        i += 1;
+    }
+    re.push('$');
+    re
+}
+
+// 翻译 `[` 之后的部分, 返回正则表达式的字符类和消耗的字符数 (包括 `]`)
+fn class(chars: &[char]) -> Option<(String, usize)> {
+    let mut re = String::from("[");
+    let mut i = 0;
+    if matches!(chars.first(), Some('!' | '^')) {
+        // 取反的字符类也不能匹配 `/`
+        re.push_str("^/");
+        i += 1;
+    }
+    // `]` 出现在最前面时是普通字符, 例如 `[]a]`
+    let first = i;
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            ']' if i > first => break,
+            '\\' => {
+                i += 1;
+                push_class_char(&mut re, *chars.get(i)?);
+            }
+            // 区间的 `-` 原样保留
+            '-' => re.push('-'),
+            c => push_class_char(&mut re, c),
+        }
+        i += 1;
+    }
+    re.push(']');
+    Some((re, i + 1))
+}
+
+fn push_literal(re: &mut String, c: char) {
+    if "\\.+*?()|[]{}^$".contains(c) {
+        re.push('\\');
+    }
+    re.push(c);
+}
+
+fn push_class_char(re: &mut String, c: char) {
+    // 正则表达式的字符类里标点符号转义后就是它本身
+    if !c.is_alphanumeric() && c != '/' {
+        re.push('\\');
+    }
+    re.push(c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Glob::new(glob).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", "ab.txt"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[!a-c]x", "bx"));
+        assert!(matches("[^a-c]x", "dx"));
+        assert!(!matches("a[!b]c", "a/c"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a+b(1).txt", "a+b(1).txt"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+        assert!(matches("[", "["));
+    }
+
+    #[test]
+    fn test_double_star() {
+        assert!(matches("**/foo", "foo"));
+        assert!(matches("**/foo", "a/b/foo"));
+        assert!(!matches("**/foo", "a/xfoo"));
+        assert!(matches("foo/**", "foo/a/b"));
+        assert!(!matches("foo/**", "bar/a"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(matches("**", "a/b/c"));
+        // 不是完整一段的 ** 和 * 一样
+        assert!(matches("a**b", "axxb"));
+        assert!(!matches("a**b", "a/b"));
+    }
+}
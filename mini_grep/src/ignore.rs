@@ -0,0 +1,330 @@
+//! 忽略规则, 兼容 gitignore 的语法
+//!
+//! 搜索目录时会读取这些文件, 优先级从低到高:
+//! 1. 全局的忽略文件: git 配置中的 `core.excludesFile`, 没有配置时是 `$XDG_CONFIG_HOME/git/ignore`
+//!    或者 `~/.config/git/ignore`
+//! 2. git 仓库中的 `.git/info/exclude`
+//! 3. 搜索的目录以及它的上级目录 (直到 git 仓库的根目录) 中的 `.gitignore` 和 `.ignore`
+//! 4. 遍历过程中每个子目录下的 `.gitignore` 和 `.ignore`, 越深的优先级越高
+//!
+//! 同一个目录下 `.ignore` 比 `.gitignore` 优先, 同一个文件中后面的规则比前面的优先.
+//! 和 ripgrep 一样, 不在 git 仓库中时也会读取 `.gitignore`.
+//!
+//! 每一行是一条规则:
+//! - 空行和以 `#` 开头的行被忽略, 行尾的空格被去掉, 需要的话用 `\#` 和 `\ ` 转义
+//! - 以 `!` 开头表示取反, 重新包含之前被忽略的路径; 被忽略的目录不会进入, 所以其中的文件无法被重新包含
+//! - 以 `/` 结尾的规则只匹配目录
+//! - 开头或中间有 `/` 的规则相对于忽略文件所在的目录, 否则匹配任意深度的文件名或目录名
+//! - 通配符的语法见 [`crate::glob`]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::glob::Glob;
+
+/// 一条忽略规则
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Glob,
+    negate: bool,
+    dir_only: bool,
+}
+
+/// 一组规则, 通常来自同一个目录下的忽略文件
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    // 规则相对的目录, 用遍历时的路径表示, 匹配时先去掉这个前缀
+    base: PathBuf,
+    // 规则所在的目录是 base 的上级目录时, base 相对于它的路径, 以 `/` 结尾
+    prefix: String,
+}
+
+impl Rules {
+    /// 解析忽略文件的内容, 规则相对于 base
+    pub fn parse(contents: &str, base: &Path) -> Rules {
+        let mut rules = Rules {
+            base: base.to_path_buf(),
+            ..Rules::default()
+        };
+        rules.add(contents);
+        rules
+    }
+
+    /// 读取 dir 下的 names 这些忽略文件, 不存在或者读不了的文件会被跳过, 没有任何规则时返回 None
+    pub fn from_dir(dir: &Path, names: &[&str]) -> Option<Rules> {
+        let mut rules = Rules::parse("", dir);
+        for name in names {
+            if let Ok(contents) = fs::read_to_string(dir.join(name)) {
+                rules.add(&contents);
+            }
+        }
+        (!rules.rules.is_empty()).then_some(rules)
+    }
+
+    fn add(&mut self, contents: &str) {
+        for line in contents.lines() {
+            // 不合法的规则和 git 一样直接跳过
+            if let Some(rule) = parse_rule(line) {
+                self.rules.push(rule);
+            }
+        }
+    }
+
+    /// path 被这一组规则忽略时返回 Some(true), 被 `!` 规则重新包含时返回 Some(false),
+    /// 没有规则匹配时返回 None, 交给优先级更低的规则决定
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let mut relative = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        relative.insert_str(0, &self.prefix);
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(&relative))
+            .map(|rule| !rule.negate)
+    }
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let line = trim_trailing_spaces(line);
+    let (negate, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    if line.is_empty() {
+        return None;
+    }
+    // 开头或中间有 `/` 时相对于忽略文件所在的目录, 否则在任意深度匹配
+    let glob = match line.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if line.contains('/') => line.to_string(),
+        None => format!("**/{}", line),
+    };
+    Some(Rule {
+        glob: Glob::new(&glob).ok()?,
+        negate,
+        dir_only,
+    })
+}
+
+// 去掉行尾没有被 `\` 转义的空格
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') {
+        let backslashes = line[..end - 1]
+            .chars()
+            .rev()
+            .take_while(|&c| c == '\\')
+            .count();
+        if backslashes % 2 == 1 {
+            break;
+        }
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// 遍历时使用的规则栈, 越靠后的优先级越高
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    stack: Vec<Rules>,
+}
+
+impl Ignore {
+    /// 搜索 root 之前, 读取全局的忽略文件, `.git/info/exclude` 和 root 上级目录中的忽略文件
+    pub fn new(root: &Path, global: Option<&Path>) -> Ignore {
+        let mut ignore = Ignore::default();
+        let Ok(absolute) = root.canonicalize() else {
+            return ignore;
+        };
+        // 从 root 的上级目录开始往上找, 直到 git 仓库的根目录
+        let mut parents = Vec::new();
+        let mut repo = None;
+        for dir in absolute.ancestors().skip(1) {
+            parents.push(dir);
+            if dir.join(".git").exists() {
+                repo = Some(dir);
+                break;
+            }
+        }
+        if absolute.join(".git").exists() {
+            parents.clear();
+            repo = Some(&absolute);
+        }
+        // 上级目录中的规则匹配的是相对于上级目录的路径, 所以要在 root 下的路径前面加上 root 相对于它的路径
+        let rules = |contents: &str, dir: &Path| {
+            let mut rules = Rules::parse(contents, root);
+            rules.prefix = absolute
+                .strip_prefix(dir)
+                .ok()
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(|p| format!("{}/", p.to_string_lossy().replace('\\', "/")))
+                .unwrap_or_default();
+            rules
+        };
+        let read = |path: &Path| fs::read_to_string(path).unwrap_or_default();
+        if let Some(global) = global {
+            let dir = repo.unwrap_or(&absolute);
+            ignore.push(rules(&read(global), dir));
+        }
+        if let Some(repo) = repo {
+            ignore.push(rules(&read(&repo.join(".git/info/exclude")), repo));
+        }
+        for dir in parents.iter().rev() {
+            let contents = read(&dir.join(".gitignore")) + "\n" + &read(&dir.join(".ignore"));
+            ignore.push(rules(&contents, dir));
+        }
+        ignore
+    }
+
+    /// 进入 dir 时调用, 返回是否读到了规则, 读到时离开 dir 之后需要调用 [`Ignore::pop`]
+    pub fn push_dir(&mut self, dir: &Path) -> bool {
+        match Rules::from_dir(dir, &[".gitignore", ".ignore"]) {
+            Some(rules) => {
+                self.stack.push(rules);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn push(&mut self, rules: Rules) {
+        if !rules.rules.is_empty() {
+            self.stack.push(rules);
+        }
+    }
+
+    pub fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    /// 从优先级最高的一组规则开始, 第一组有规则匹配的决定 path 是否被忽略
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|rules| rules.matched(path, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+/// 全局忽略文件的路径: git 配置中的 `core.excludesFile`, 没有配置时是 git 的默认位置
+pub fn global_excludes_file() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let xdg = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+    let configs = [
+        home.as_ref().map(|h| h.join(".gitconfig")),
+        xdg.as_ref().map(|x| x.join("git/config")),
+    ];
+    let configured = configs
+        .iter()
+        .flatten()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .find_map(|contents| excludes_file_from_config(&contents));
+    match configured {
+        Some(path) => match (path.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(path)),
+        },
+        None => xdg.map(|x| x.join("git/ignore")),
+    }
+}
+
+// 只支持最常见的写法: `[core]` 段中的 `excludesFile = PATH`, 名字不区分大小写
+fn excludes_file_from_config(contents: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut found = None;
+    for line in contents.lines().map(str::trim) {
+        if let Some(section) = line.strip_prefix('[') {
+            in_core = section
+                .trim_end_matches(']')
+                .trim()
+                .eq_ignore_ascii_case("core");
+        } else if let Some((key, value)) = line.split_once('=') {
+            if in_core && key.trim().eq_ignore_ascii_case("excludesfile") {
+                // 后面的配置覆盖前面的
+                found = Some(value.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let rules = Rules::parse(
+            "# comment\n\
+             *.log\n\
+             !keep.log\n\
+             target/\n\
+             /root.txt\n\
+             doc/*.md\n\
+             trailing \n\
+             escaped\\ \n\
+             \\#hash\n",
+            Path::new("repo"),
+        );
+        let ignored =
+            |path: &str, is_dir: bool| rules.matched(&Path::new("repo").join(path), is_dir);
+        assert_eq!(ignored("a.log", false), Some(true));
+        assert_eq!(ignored("src/b.log", false), Some(true));
+        assert_eq!(ignored("src/keep.log", false), Some(false));
+        assert_eq!(ignored("target", true), Some(true));
+        assert_eq!(ignored("src/target", true), Some(true));
+        // 以 / 结尾的规则只匹配目录
+        assert_eq!(ignored("target", false), None);
+        assert_eq!(ignored("root.txt", false), Some(true));
+        assert_eq!(ignored("src/root.txt", false), None);
+        assert_eq!(ignored("doc/a.md", false), Some(true));
+        assert_eq!(ignored("src/doc/a.md", false), None);
+        assert_eq!(ignored("doc/x/a.md", false), None);
+        assert_eq!(ignored("trailing", false), Some(true));
+        assert_eq!(ignored("escaped ", false), Some(true));
+        assert_eq!(ignored("#hash", false), Some(true));
+        assert_eq!(ignored("main.rs", false), None);
+    }
+
+    #[test]
+    fn test_precedence() {
+        let mut ignore = Ignore::default();
+        ignore.push(Rules::parse("*.txt\n", Path::new("repo")));
+        ignore.push(Rules::parse("!*.txt\n", Path::new("repo/sub")));
+        assert!(ignore.is_ignored(Path::new("repo/a.txt"), false));
+        // 更深的目录中的规则优先
+        assert!(!ignore.is_ignored(Path::new("repo/sub/a.txt"), false));
+        ignore.pop();
+        assert!(ignore.is_ignored(Path::new("repo/sub/a.txt"), false));
+    }
+
+    #[test]
+    fn test_excludes_file_from_config() {
+        let config = "[user]\n\tname = x\n[core]\n\texcludesFile = ~/.gitignore_global\n";
+        assert_eq!(
+            excludes_file_from_config(config).as_deref(),
+            Some("~/.gitignore_global")
+        );
+        assert_eq!(
+            excludes_file_from_config("[alias]\nexcludesfile = x\n"),
+            None
+        );
+    }
+}
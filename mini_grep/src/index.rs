@@ -0,0 +1,733 @@
+//! 持久化的三元组 (trigram) 索引, 加速对同一个目录的反复搜索
+//!
+//! `mini_grep index DIR` 在 `DIR/.mini_grep_index` 中记录每个文件包含哪些三元组 (连续的 3 个字节).
+//! 字面匹配的每个结果都包含查询字符串的所有三元组, 所以 `--index` 搜索时, 只有包含全部三元组的文件才需要读取.
+//! 三元组取自大小写折叠 (见 [`crate::fold`]) 之后的文本, 所以同一个索引可以用于区分和忽略大小写的搜索.
+//!
+//! 再次运行 `mini_grep index` 时只重新读取修改时间或大小变了的文件, 删除已经不存在的文件.
+//! 搜索时修改时间或大小和索引中不同的文件, 以及索引中没有的文件, 总是会被搜索, 所以过期的索引只会变慢, 不会漏掉结果.
+//! 文件系统记录的修改时间精度有限 (有的只精确到 2 秒), 读取之后在同一个时间单位内又被修改的文件, 修改时间可能不变.
+//! 所以和 git 的 index 类似, 修改时间离开始建立索引不到 2 秒的文件也被当成已修改.
+//!
+//! 以下情况不使用三元组过滤, 仍然搜索所有文件: 查询字符串折叠后少于 3 个字节, 正则中有元字符, --fuzzy,
+//! -v, -c, -L 以及 -z.
+//!
+//! # 文件格式
+//! 所有整数都是小端序:
+//! ```text
+//! magic      8 字节 "MGINDEX\0"
+//! version    u32, 目前是 1, 读到其他版本时搜索会报错, index 会重新建立索引
+//! started    u64 秒 + u32 纳秒, 开始建立索引的时间
+//! files      u32 文件数, 然后每个文件: u32 路径长度, 路径 (UTF-8, 相对于索引所在的目录, 用 `/` 分隔),
+//!            u64 秒 + u32 纳秒的修改时间, u64 文件大小, u8 是否是二进制文件
+//! trigrams   u32 三元组数, 然后按三元组从小到大: u32 三元组, u32 倒排列表的字节数
+//! postings   每个三元组的倒排列表依次排列: 包含它的文件编号, 从小到大, 相邻两个的差用 LEB128 编码
+//! ```
+
+use std::collections::hash_map::Entry as Slot;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::fold::fold;
+use crate::walk::{self, WalkOptions};
+use crate::{Config, Error, MatchMode, OutputMode};
+
+/// 索引文件的名字, 放在建立索引的目录下
+pub const INDEX_FILE: &str = ".mini_grep_index";
+
+/// 当前的索引格式版本, 格式变化时加 1
+pub const VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"MGINDEX\0";
+
+// 文件的修改时间, 精确到纳秒
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+struct Stamp {
+    secs: u64,
+    nanos: u32,
+}
+
+// 修改时间离开始建立索引至少这么多秒的文件, 才相信它在索引中的内容
+const SETTLE_SECS: u64 = 2;
+
+impl Stamp {
+    fn new(time: SystemTime) -> Stamp {
+        // 早于 1970 年的时间当成 0, 只会让这个文件总是被当成已修改
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Stamp {
+            secs: since.as_secs(),
+            nanos: since.subsec_nanos(),
+        }
+    }
+}
+
+// 索引中的一个文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    path: String,
+    mtime: Stamp,
+    size: u64,
+    binary: bool,
+}
+
+/// 打开的索引. 文件列表和三元组表在打开时读入内存, 倒排列表在查询时才读取
+pub struct Index {
+    dir: PathBuf,
+    started: Stamp,
+    files: Vec<Entry>,
+    by_path: HashMap<String, usize>,
+    // (三元组, 倒排列表在 postings 部分中的偏移, 字节数), 按三元组排序
+    table: Vec<(u32, u64, u32)>,
+    // postings 部分在文件中的开始位置
+    postings: u64,
+    reader: BufReader<File>,
+}
+
+impl Index {
+    /// 打开 dir 下的索引, 没有索引时返回 None. 格式不对或者版本不同时返回 InvalidData 错误
+    pub fn open(dir: &Path) -> io::Result<Option<Index>> {
+        let path = dir.join(INDEX_FILE);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a mini_grep index"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid(&format!(
+                "index version {} is not supported (expected {}), rebuild it with 'mini_grep index'",
+                version, VERSION
+            )));
+        }
+        let started = read_stamp(&mut reader)?;
+        let count = read_u32(&mut reader)? as usize;
+        let mut files = Vec::with_capacity(count.min(1 << 20));
+        for _ in 0..count {
+            let len = read_u32(&mut reader)? as usize;
+            let mut path = vec![0; len];
+            reader.read_exact(&mut path)?;
+            files.push(Entry {
+                path: String::from_utf8(path).map_err(|_| invalid("path is not UTF-8"))?,
+                mtime: read_stamp(&mut reader)?,
+                size: read_u64(&mut reader)?,
+                binary: read_u8(&mut reader)? != 0,
+            });
+        }
+        let count = read_u32(&mut reader)? as usize;
+        let mut table = Vec::with_capacity(count.min(1 << 24));
+        let mut offset = 0;
+        for _ in 0..count {
+            let trigram = read_u32(&mut reader)?;
+            let len = read_u32(&mut reader)?;
+            table.push((trigram, offset, len));
+            offset += u64::from(len);
+        }
+        let postings = reader.stream_position()?;
+        let by_path = files
+            .iter()
+            .enumerate()
+            .map(|(id, entry)| (entry.path.clone(), id))
+            .collect();
+        Ok(Some(Index {
+            dir: dir.to_path_buf(),
+            started,
+            files,
+            by_path,
+            table,
+            postings,
+            reader,
+        }))
+    }
+
+    /// 索引中的文件数
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    // 包含 trigram 的文件编号, 从小到大
+    fn postings(&mut self, trigram: u32) -> io::Result<Vec<u32>> {
+        let Ok(i) = self.table.binary_search_by_key(&trigram, |&(t, _, _)| t) else {
+            return Ok(Vec::new());
+        };
+        let (_, offset, len) = self.table[i];
+        self.reader.seek(SeekFrom::Start(self.postings + offset))?;
+        let mut bytes = vec![0; len as usize];
+        self.reader.read_exact(&mut bytes)?;
+        let mut ids = Vec::new();
+        let mut pos = 0;
+        let mut last = 0;
+        while pos < bytes.len() {
+            let (delta, used) =
+                read_varint(&bytes[pos..]).ok_or_else(|| invalid("bad posting list"))?;
+            last += delta;
+            ids.push(last);
+            pos += used;
+        }
+        if ids
+            .last()
+            .is_some_and(|&id| id as usize >= self.files.len())
+        {
+            return Err(invalid("bad posting list"));
+        }
+        Ok(ids)
+    }
+
+    // 读出所有倒排列表, 转换成每个文件的三元组, 用于增量更新
+    fn trigrams_by_file(&mut self) -> io::Result<Vec<Vec<u32>>> {
+        let mut by_file = vec![Vec::new(); self.files.len()];
+        let trigrams: Vec<u32> = self.table.iter().map(|&(t, _, _)| t).collect();
+        for trigram in trigrams {
+            for id in self.postings(trigram)? {
+                by_file[id as usize].push(trigram);
+            }
+        }
+        Ok(by_file)
+    }
+
+    // key 对应的文件在建立索引之后没有变过时, 返回它的编号
+    fn unchanged(&self, key: &str, meta: &fs::Metadata) -> Option<usize> {
+        let &id = self.by_path.get(key)?;
+        let entry = &self.files[id];
+        let mtime = Stamp::new(meta.modified().ok()?);
+        let settled = mtime.secs + SETTLE_SECS <= self.started.secs;
+        (entry.mtime == mtime && entry.size == meta.len() && settled).then_some(id)
+    }
+
+    /// 每一组三元组都是 "与" 的关系, 组和组之间是 "或" 的关系, 返回每个文件是否可能匹配
+    fn candidates(&mut self, query: &[Vec<u32>]) -> io::Result<Vec<bool>> {
+        let mut result = vec![false; self.files.len()];
+        let mut cache: HashMap<u32, Vec<u32>> = HashMap::new();
+        for group in query {
+            let mut matched: Option<Vec<u32>> = None;
+            for &trigram in group {
+                let ids = match cache.entry(trigram) {
+                    Slot::Occupied(entry) => entry.into_mut(),
+                    Slot::Vacant(entry) => entry.insert(self.postings(trigram)?),
+                };
+                matched = Some(match matched {
+                    None => ids.clone(),
+                    Some(prev) => prev
+                        .into_iter()
+                        .filter(|id| ids.binary_search(id).is_ok())
+                        .collect(),
+                });
+            }
+            for id in matched.unwrap_or_default() {
+                result[id as usize] = true;
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// `mini_grep index` 的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// 索引文件的路径
+    pub path: PathBuf,
+    /// 索引中的文件数
+    pub files: usize,
+    /// 新加入, 重新读取和删除的文件数
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// 旧的索引无法读取 (例如版本不同) 而重新建立时为 true
+    pub rebuilt: bool,
+    /// 因为无法读取而跳过的文件和目录数量
+    pub errors: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "indexed {} files in {}: {} added, {} updated, {} removed",
+            self.files,
+            self.path.display(),
+            self.added,
+            self.updated,
+            self.removed
+        )?;
+        if self.rebuilt {
+            write!(f, " (old index rebuilt)")?;
+        }
+        Ok(())
+    }
+}
+
+/// 建立或者增量更新 dir 的索引, 需要索引哪些文件由 opts 决定 (opts.binary 被忽略, 二进制文件只记录不索引内容).
+/// 无法读取的文件和目录会在标准错误中报告, 然后跳过
+pub fn update(dir: &Path, opts: &WalkOptions) -> Result<Stats, Error> {
+    if !dir.is_dir() {
+        return Err(Error::Args(format!(
+            "index: '{}' is not a directory",
+            dir.display()
+        )));
+    }
+    let started = Stamp::new(SystemTime::now());
+    let mut stats = Stats {
+        path: dir.join(INDEX_FILE),
+        ..Stats::default()
+    };
+    let mut old = match Index::open(dir) {
+        Ok(old) => old,
+        Err(err)
+            if [io::ErrorKind::InvalidData, io::ErrorKind::UnexpectedEof].contains(&err.kind()) =>
+        {
+            stats.rebuilt = true;
+            None
+        }
+        Err(err) => return Err(Error::file(&stats.path, err)),
+    };
+    let mut old_trigrams = match &mut old {
+        Some(index) => index
+            .trigrams_by_file()
+            .map_err(|e| Error::file(&stats.path, e))?,
+        None => Vec::new(),
+    };
+    let opts = WalkOptions {
+        binary: true,
+        ..opts.clone()
+    };
+    let (files, walk_errors) = walk::files(dir, &opts);
+    walk_errors.iter().for_each(crate::report);
+    stats.errors = walk_errors.len();
+    let mut entries = Vec::new();
+    let mut trigrams = Vec::new();
+    let mut seen = vec![false; old.as_ref().map_or(0, Index::len)];
+    for path in &files {
+        let Some(key) = relative_key(dir, path) else {
+            continue;
+        };
+        if key.starts_with(INDEX_FILE) {
+            continue;
+        }
+        let meta = match fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(err) => {
+                crate::report(&Error::file(path, err));
+                stats.errors += 1;
+                continue;
+            }
+        };
+        if let Some(id) = old.as_ref().and_then(|index| index.unchanged(&key, &meta)) {
+            seen[id] = true;
+            entries.push(old.as_ref().unwrap().files[id].clone());
+            trigrams.push(std::mem::take(&mut old_trigrams[id]));
+            continue;
+        }
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                crate::report(&Error::file(path, err));
+                stats.errors += 1;
+                continue;
+            }
+        };
+        match old.as_ref().and_then(|index| index.by_path.get(&key)) {
+            Some(&id) => {
+                seen[id] = true;
+                stats.updated += 1;
+            }
+            None => stats.added += 1,
+        }
+        let binary = walk::is_binary(&contents[..contents.len().min(walk::BINARY_SNIFF_LEN)]);
+        trigrams.push(if binary {
+            Vec::new()
+        } else {
+            text_trigrams(&String::from_utf8_lossy(&contents))
+        });
+        entries.push(Entry {
+            path: key,
+            // 用读取之前的修改时间, 读取过程中被修改的文件下次会被重新读取
+            mtime: meta.modified().map(Stamp::new).unwrap_or_default(),
+            size: meta.len(),
+            binary,
+        });
+    }
+    stats.removed = seen.iter().filter(|&&seen| !seen).count();
+    stats.files = entries.len();
+    // 先关闭旧的索引再覆盖它
+    drop(old);
+    write_index(&stats.path, started, &entries, &trigrams)
+        .map_err(|e| Error::file(&stats.path, e))?;
+    Ok(stats)
+}
+
+// 写到同一个目录下的临时文件, 再 rename 覆盖旧的索引, 正在搜索的进程不会读到写了一半的索引
+fn write_index(
+    path: &Path,
+    started: Stamp,
+    entries: &[Entry],
+    trigrams: &[Vec<u32>],
+) -> io::Result<()> {
+    let mut postings: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+    let mut last: HashMap<u32, u32> = HashMap::new();
+    for (id, list) in trigrams.iter().enumerate() {
+        let id = id as u32;
+        for &trigram in list {
+            let prev = last.insert(trigram, id);
+            // 第一个编号的差是编号本身
+            let delta = prev.map_or(id, |prev| id - prev);
+            write_varint(postings.entry(trigram).or_default(), delta);
+        }
+    }
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(format!(".{}.tmp", process::id()));
+    let tmp = PathBuf::from(tmp);
+    let result = (|| {
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        write_stamp(&mut out, started)?;
+        out.write_all(&(entries.len() as u32).to_le_bytes())?;
+        for entry in entries {
+            out.write_all(&(entry.path.len() as u32).to_le_bytes())?;
+            out.write_all(entry.path.as_bytes())?;
+            write_stamp(&mut out, entry.mtime)?;
+            out.write_all(&entry.size.to_le_bytes())?;
+            out.write_all(&[u8::from(entry.binary)])?;
+        }
+        out.write_all(&(postings.len() as u32).to_le_bytes())?;
+        for (trigram, list) in &postings {
+            out.write_all(&trigram.to_le_bytes())?;
+            out.write_all(&(list.len() as u32).to_le_bytes())?;
+        }
+        for list in postings.values() {
+            out.write_all(list)?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// 用 root 所在目录 (或者它的上级目录) 的索引, 挑出 root 下需要搜索的文件, 结果和 [`walk::files`] 一样按路径排序
+///
+/// 没有变过的文件根据索引判断, 不用读取; 二进制文件也根据索引跳过, 不用再读开头判断.
+/// 找不到索引时返回错误
+pub fn files(
+    conf: &Config,
+    root: &Path,
+    opts: &WalkOptions,
+) -> Result<(Vec<PathBuf>, Vec<Error>), Error> {
+    let absolute = root.canonicalize().map_err(|e| Error::file(root, e))?;
+    let mut index = None;
+    for dir in absolute.ancestors() {
+        if let Some(found) = Index::open(dir).map_err(|e| Error::file(dir.join(INDEX_FILE), e))? {
+            index = Some(found);
+            break;
+        }
+    }
+    let mut index = index.ok_or_else(|| {
+        Error::Args(format!(
+            "no index found for '{}', run 'mini_grep index DIR' first",
+            root.display()
+        ))
+    })?;
+    let candidates = match query_trigrams(conf) {
+        Some(query) => Some(
+            index
+                .candidates(&query)
+                .map_err(|e| Error::file(index.dir.join(INDEX_FILE), e))?,
+        ),
+        None => None,
+    };
+    let walk_opts = WalkOptions {
+        binary: true,
+        ..opts.clone()
+    };
+    let (files, errors) = walk::files(root, &walk_opts);
+    let prefix = absolute.strip_prefix(&index.dir).unwrap_or(Path::new(""));
+    let mut selected = Vec::new();
+    for path in files {
+        let key = path
+            .strip_prefix(root)
+            .ok()
+            .and_then(|rest| relative_key(Path::new(""), &prefix.join(rest)));
+        let meta = fs::metadata(&path);
+        let id = match (&key, &meta) {
+            (Some(key), Ok(meta)) => index.unchanged(key, meta),
+            _ => None,
+        };
+        let keep = match id {
+            Some(id) if index.files[id].binary => opts.binary,
+            Some(id) => candidates.as_ref().is_none_or(|c| c[id]),
+            // 不在索引中或者已经修改过的文件照常处理, 读不了的文件留给搜索时报告
+            None => opts.binary || !walk::is_binary_file(&path, opts.decompress).unwrap_or(false),
+        };
+        if keep {
+            selected.push(path);
+        }
+    }
+    Ok((selected, errors))
+}
+
+/// 从 conf 中提取查询需要的三元组, 返回的每一组对应一个模式, 文件要包含某一组的全部三元组才可能匹配.
+/// 返回 None 表示不能用三元组过滤, 所有文件都要搜索
+pub fn query_trigrams(conf: &Config) -> Option<Vec<Vec<u32>>> {
+    // -v, -c 和 -L 需要处理没有匹配的文件, -z 时索引中的内容和搜索的内容不一样
+    let every_file = [OutputMode::Count, OutputMode::FilesWithoutMatch].contains(&conf.output);
+    if conf.invert || conf.decompress || every_file {
+        return None;
+    }
+    let literal = match conf.mode {
+        MatchMode::Literal => true,
+        // 没有元字符的正则就是字面匹配
+        MatchMode::Regex => conf
+            .queries()
+            .iter()
+            .all(|p| !p.contains(|c| "\\.+*?()|[]{}^$".contains(c))),
+        MatchMode::Fuzzy(_) => false,
+    };
+    if !literal {
+        return None;
+    }
+    conf.queries()
+        .iter()
+        .map(|pattern| {
+            let trigrams = text_trigrams(pattern);
+            // 太短的模式没有三元组, 任何文件都可能匹配
+            (!trigrams.is_empty()).then_some(trigrams)
+        })
+        .collect()
+}
+
+/// text 大小写折叠之后的所有三元组, 从小到大排列, 没有重复. 每个三元组是 3 个字节组成的 24 位整数
+pub fn text_trigrams(text: &str) -> Vec<u32> {
+    let mut folded = Vec::with_capacity(text.len());
+    let mut buf = [0; 4];
+    for c in text.chars() {
+        if c.is_ascii() {
+            folded.push(c.to_ascii_lowercase() as u8);
+        } else {
+            for f in fold(c) {
+                folded.extend_from_slice(f.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    let mut trigrams: Vec<u32> = folded
+        .windows(3)
+        .map(|w| u32::from(w[0]) << 16 | u32::from(w[1]) << 8 | u32::from(w[2]))
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+// path 相对于 dir 的路径, 用 `/` 分隔. 不是 UTF-8 的路径不放进索引, 返回 None
+fn relative_key(dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?;
+    let parts: Option<Vec<&str>> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect();
+    Some(parts?.join("/"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid index: {}", msg),
+    )
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_stamp(reader: &mut impl Read) -> io::Result<Stamp> {
+    Ok(Stamp {
+        secs: read_u64(reader)?,
+        nanos: read_u32(reader)?,
+    })
+}
+
+fn write_stamp(out: &mut impl Write, stamp: Stamp) -> io::Result<()> {
+    out.write_all(&stamp.secs.to_le_bytes())?;
+    out.write_all(&stamp.nanos.to_le_bytes())
+}
+
+// LEB128: 每个字节存 7 位, 最高位为 1 表示后面还有字节
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// 返回值和用掉的字节数, 数据不完整或者超过 u32 时返回 None
+fn read_varint(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, &b) in bytes.iter().enumerate().take(5) {
+        value |= u32::from(b & 0x7f).checked_shl(7 * i as u32)?;
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_trigrams_and_varint() {
+        let abc = u32::from(b'a') << 16 | u32::from(b'b') << 8 | u32::from(b'c');
+        assert_eq!(text_trigrams("ABCabc"), {
+            let mut expected = vec![abc, text_trigrams("bca")[0], text_trigrams("cab")[0]];
+            expected.sort();
+            expected
+        });
+        // 折叠之后 STRASSE 和 Straße 的三元组一样
+        assert_eq!(text_trigrams("STRASSE"), text_trigrams("Straße"));
+        assert!(text_trigrams("ab").is_empty());
+
+        let mut buf = Vec::new();
+        for value in [0, 127, 128, 300, u32::MAX] {
+            buf.clear();
+            write_varint(&mut buf, value);
+            assert_eq!(read_varint(&buf), Some((value, buf.len())));
+        }
+        assert_eq!(read_varint(&[0x80]), None);
+    }
+
+    #[test]
+    fn test_query_trigrams() {
+        let conf = Config {
+            query: String::from("fast"),
+            ..Config::default()
+        };
+        assert_eq!(query_trigrams(&conf), Some(vec![text_trigrams("fast")]));
+        let regex = Config {
+            mode: MatchMode::Regex,
+            ..conf.clone()
+        };
+        assert!(query_trigrams(&regex).is_some());
+        let regex = Config {
+            query: String::from("fa.t"),
+            ..regex
+        };
+        assert_eq!(query_trigrams(&regex), None);
+        let invert = Config {
+            invert: true,
+            ..conf.clone()
+        };
+        assert_eq!(query_trigrams(&invert), None);
+        // 任何一个模式太短, 所有文件都可能匹配
+        let patterns = Config {
+            patterns: Some(vec![String::from("fast"), String::from("go")]),
+            ..conf
+        };
+        assert_eq!(query_trigrams(&patterns), None);
+    }
+
+    #[test]
+    fn test_update_and_search() {
+        let root = env::temp_dir().join(format!("mini_grep_index_{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("a.txt"), "safe, fast, productive.").unwrap();
+        fs::write(root.join("src/b.txt"), "Pick three.").unwrap();
+        fs::write(root.join("src/c.bin"), b"fast\x00").unwrap();
+        let opts = WalkOptions::default();
+        // 刚修改过的文件总是会被当成已修改, 所以先把修改时间改到之前
+        let settle = |path: &Path| {
+            let file = File::options().write(true).open(root.join(path)).unwrap();
+            let time = SystemTime::now() - std::time::Duration::from_secs(60);
+            file.set_modified(time).unwrap();
+        };
+        for path in ["a.txt", "src/b.txt", "src/c.bin"] {
+            settle(Path::new(path));
+        }
+        let stats = update(&root, &opts).unwrap();
+        assert_eq!(
+            (stats.files, stats.added, stats.updated, stats.removed),
+            (3, 3, 0, 0)
+        );
+
+        let search = |query: &str, dir: &Path| -> Vec<String> {
+            let conf = Config {
+                query: query.to_string(),
+                ignore_case: true,
+                ..Config::default()
+            };
+            let (files, _) = files(&conf, dir, &opts).unwrap();
+            files
+                .iter()
+                .map(|p| p.strip_prefix(&root).unwrap().display().to_string())
+                .collect()
+        };
+        assert_eq!(search("FAST", &root), ["a.txt"]);
+        assert_eq!(search("three", &root), ["src/b.txt"]);
+        assert!(search("nothing", &root).is_empty());
+        // 在子目录中搜索时使用上级目录的索引
+        assert_eq!(search("three", &root.join("src")), ["src/b.txt"]);
+        // 太短的查询不过滤
+        assert_eq!(search("fa", &root), ["a.txt", "src/b.txt"]);
+
+        // 修改过的文件不再相信索引, 新文件也会被搜索
+        fs::write(root.join("src/b.txt"), "fast food").unwrap();
+        fs::write(root.join("d.txt"), "fast").unwrap();
+        assert_eq!(search("fast", &root), ["a.txt", "d.txt", "src/b.txt"]);
+        fs::remove_file(root.join("a.txt")).unwrap();
+        let stats = update(&root, &opts).unwrap();
+        // c.bin 没有变, 不用重新读取
+        assert_eq!(
+            (stats.files, stats.added, stats.updated, stats.removed),
+            (3, 1, 1, 1)
+        );
+        // b.txt 和 d.txt 刚被修改过, 再次更新时仍然会重新读取
+        assert_eq!(update(&root, &opts).unwrap().updated, 2);
+        assert_eq!(search("fast", &root), ["d.txt", "src/b.txt"]);
+
+        // 版本不同的索引: 搜索报错, index 重新建立
+        let path = root.join(INDEX_FILE);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[8] = 99;
+        fs::write(&path, bytes).unwrap();
+        let conf = Config {
+            query: String::from("fast"),
+            ..Config::default()
+        };
+        let err = files(&conf, &root, &opts).unwrap_err().to_string();
+        assert!(err.contains("index version 99 is not supported"), "{}", err);
+        assert!(update(&root, &opts).unwrap().rebuilt);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
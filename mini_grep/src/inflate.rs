@@ -0,0 +1,668 @@
+//! 流式的 deflate 解压 (RFC 1951), 以及 gzip (RFC 1952) 和 zlib (RFC 1950) 格式
+//!
+//! [`Decoder`] 实现了 [`Read`], 每次只解压调用者需要的数据, 内存占用只有 32 KiB 的滑动窗口和
+//! 当前块的 Huffman 表, 和压缩文件的大小无关. gzip 的 CRC-32 和 zlib 的 Adler-32 校验和都会检查,
+//! 多个 gzip 成员连接在一起的文件 (例如 `cat a.gz b.gz`) 会被连续解压.
+//!
+//! Huffman 解码使用一张按最长编码长度展开的查找表, 一次查表就能得到符号和它的长度.
+
+use std::io::{self, BufRead, BufReader, Read};
+
+// 滑动窗口的大小, deflate 的距离最大是 32768
+const WINDOW_SIZE: usize = 1 << 15;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+
+// 长度符号 257..=285 对应的基础长度和额外位数
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// 距离符号 0..=29 对应的基础距离和额外位数
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// 动态块中, 编码长度的编码长度按这个顺序排列
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// 压缩数据的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// 没有头部和校验和的 deflate 数据
+    Raw,
+    /// zlib: 2 字节头部 + deflate + Adler-32
+    Zlib,
+    /// gzip: 可变长度的头部 + deflate + CRC-32 和原始长度
+    Gzip,
+}
+
+/// 根据开头的字节判断压缩格式, 不是 gzip 或 zlib 时返回 None
+///
+/// raw deflate 没有头部, 无法识别. zlib 的头部只有两个字节, 为了不把以 `x^` 开头的文本误认为 zlib,
+/// 只识别 zlib 实际使用的 `78 01`, `78 9c` 和 `78 da`
+pub fn detect(header: &[u8]) -> Option<Format> {
+    match header {
+        [0x1f, 0x8b, ..] => Some(Format::Gzip),
+        [0x78, 0x01 | 0x9c | 0xda, ..] => Some(Format::Zlib),
+        _ => None,
+    }
+}
+
+/// 开头是 gzip 或 zlib 数据时返回解压它的 reader, 否则原样返回 reader
+pub fn auto<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    Ok(match detect(reader.fill_buf()?) {
+        Some(format) => Box::new(BufReader::new(Decoder::new(reader, format))),
+        None => Box::new(reader),
+    })
+}
+
+/// 从 inner 中读取压缩数据, 读出解压后的数据
+pub struct Decoder<R: BufRead> {
+    bits: Bits<R>,
+    format: Format,
+    state: State,
+    window: Box<[u8]>,
+    // 已经输出的字节数, 同时也是下一个字节在窗口中的位置
+    written: u64,
+    // 当前成员解压后数据的校验和, gzip 是 CRC-32, zlib 是 Adler-32
+    checksum: u32,
+}
+
+enum State {
+    // 读取 gzip / zlib 的头部
+    Header,
+    // 读取块头部, 参数表示上一个块是否是最后一个
+    Block { last: bool },
+    Stored { remaining: usize, last: bool },
+    Codes(Box<Codes>),
+    Done,
+}
+
+// 使用 Huffman 编码的块, copy 是还没有输出完的 (长度, 距离)
+struct Codes {
+    literal: Huffman,
+    distance: Huffman,
+    copy: Option<(usize, usize)>,
+    last: bool,
+}
+
+impl<R: BufRead> Decoder<R> {
+    pub fn new(inner: R, format: Format) -> Decoder<R> {
+        Decoder {
+            bits: Bits::new(inner),
+            format,
+            state: State::Header,
+            window: vec![0; WINDOW_SIZE].into_boxed_slice(),
+            written: 0,
+            checksum: initial_checksum(format),
+        }
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        match self.format {
+            Format::Raw => {}
+            Format::Zlib => {
+                let cmf = self.bits.byte()?;
+                let flg = self.bits.byte()?;
+                if cmf & 0x0f != 8 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+                    return Err(invalid("invalid zlib header"));
+                }
+                if flg & 0x20 != 0 {
+                    return Err(invalid("zlib preset dictionaries are not supported"));
+                }
+            }
+            Format::Gzip => self.gzip_header()?,
+        }
+        self.state = State::Block { last: false };
+        Ok(())
+    }
+
+    fn gzip_header(&mut self) -> io::Result<()> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+        if self.bits.byte()? != 0x1f || self.bits.byte()? != 0x8b {
+            return Err(invalid("invalid gzip header"));
+        }
+        if self.bits.byte()? != 8 {
+            return Err(invalid("unsupported gzip compression method"));
+        }
+        let flags = self.bits.byte()?;
+        // MTIME (4), XFL (1), OS (1)
+        for _ in 0..6 {
+            self.bits.byte()?;
+        }
+        if flags & FEXTRA != 0 {
+            let len = self.bits.bits(16)?;
+            for _ in 0..len {
+                self.bits.byte()?;
+            }
+        }
+        // 文件名和注释都以 0 结尾
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                while self.bits.byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            self.bits.bits(16)?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self) -> io::Result<()> {
+        let last = self.bits.bits(1)? == 1;
+        self.state = match self.bits.bits(2)? {
+            0 => {
+                self.bits.align();
+                let len = self.bits.bits(16)?;
+                let nlen = self.bits.bits(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(invalid("invalid stored block length"));
+                }
+                State::Stored {
+                    remaining: len as usize,
+                    last,
+                }
+            }
+            1 => State::Codes(Box::new(Codes {
+                literal: Huffman::fixed_literal(),
+                distance: Huffman::fixed_distance(),
+                copy: None,
+                last,
+            })),
+            2 => State::Codes(Box::new(self.dynamic(last)?)),
+            _ => return Err(invalid("invalid block type")),
+        };
+        Ok(())
+    }
+
+    fn dynamic(&mut self, last: bool) -> io::Result<Codes> {
+        let literals = self.bits.bits(5)? as usize + 257;
+        let distances = self.bits.bits(5)? as usize + 1;
+        let code_lengths = self.bits.bits(4)? as usize + 4;
+        let mut lengths = [0u8; 19];
+        for &index in &CODE_LENGTH_ORDER[..code_lengths] {
+            lengths[index] = self.bits.bits(3)? as u8;
+        }
+        let code = Huffman::new(&lengths)?;
+        // 字面量/长度和距离的编码长度是连续编码的, 重复可以跨越两者的边界
+        let mut lengths = vec![0u8; literals + distances];
+        let mut i = 0;
+        while i < lengths.len() {
+            let symbol = code.decode(&mut self.bits)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 if i > 0 => (lengths[i - 1], 3 + self.bits.bits(2)? as usize),
+                17 => (0, 3 + self.bits.bits(3)? as usize),
+                18 => (0, 11 + self.bits.bits(7)? as usize),
+                _ => return Err(invalid("invalid code lengths")),
+            };
+            if i + repeat > lengths.len() {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths[i..i + repeat].fill(value);
+            i += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("missing end-of-block code"));
+        }
+        Ok(Codes {
+            literal: Huffman::new(&lengths[..literals])?,
+            distance: Huffman::new(&lengths[literals..])?,
+            copy: None,
+            last,
+        })
+    }
+
+    fn trailer(&mut self) -> io::Result<()> {
+        self.bits.align();
+        match self.format {
+            Format::Raw => {}
+            Format::Zlib => {
+                // Adler-32 是大端序的
+                let mut expected = 0;
+                for _ in 0..4 {
+                    expected = expected << 8 | u32::from(self.bits.byte()?);
+                }
+                if expected != self.checksum {
+                    return Err(invalid("zlib checksum mismatch"));
+                }
+            }
+            Format::Gzip => {
+                let crc = self.bits.bits(32)?;
+                let size = self.bits.bits(32)?;
+                if crc != !self.checksum {
+                    return Err(invalid("gzip CRC mismatch"));
+                }
+                // ISIZE 是原始长度对 2^32 取模
+                if size != self.written as u32 {
+                    return Err(invalid("gzip length mismatch"));
+                }
+                // 后面紧跟着另一个 gzip 成员时继续解压, 其他多余的数据和 gzip 一样忽略
+                if self.bits.peek_bytes()?.starts_with(&[0x1f, 0x8b]) {
+                    self.written = 0;
+                    self.checksum = initial_checksum(self.format);
+                    self.state = State::Header;
+                    return Ok(());
+                }
+            }
+        }
+        self.state = State::Done;
+        Ok(())
+    }
+
+    // 把 byte 写到 buf 和滑动窗口中
+    fn push(&mut self, byte: u8, buf: &mut [u8], n: &mut usize) {
+        self.window[self.written as usize & WINDOW_MASK] = byte;
+        self.written += 1;
+        buf[*n] = byte;
+        *n += 1;
+    }
+
+    // 解压 Huffman 编码的块, 直到 buf 写满或者块结束
+    fn codes(&mut self, codes: &mut Codes, buf: &mut [u8], n: &mut usize) -> io::Result<bool> {
+        loop {
+            if let Some((len, dist)) = &mut codes.copy {
+                while *len > 0 && *n < buf.len() {
+                    let byte =
+                        self.window[(self.written as usize).wrapping_sub(*dist) & WINDOW_MASK];
+                    self.push(byte, buf, n);
+                    *len -= 1;
+                }
+                if *len > 0 {
+                    return Ok(false);
+                }
+                codes.copy = None;
+            }
+            if *n == buf.len() {
+                return Ok(false);
+            }
+            let symbol = codes.literal.decode(&mut self.bits)?;
+            match symbol {
+                0..=255 => self.push(symbol as u8, buf, n),
+                256 => return Ok(true),
+                257..=285 => {
+                    let index = usize::from(symbol - 257);
+                    let extra = self.bits.bits(LENGTH_EXTRA[index].into())?;
+                    let len = usize::from(LENGTH_BASE[index]) + extra as usize;
+                    let index = usize::from(codes.distance.decode(&mut self.bits)?);
+                    if index >= DIST_BASE.len() {
+                        return Err(invalid("invalid distance code"));
+                    }
+                    let extra = self.bits.bits(DIST_EXTRA[index].into())?;
+                    let dist = usize::from(DIST_BASE[index]) + extra as usize;
+                    if dist as u64 > self.written {
+                        return Err(invalid("distance too far back"));
+                    }
+                    codes.copy = Some((len, dist));
+                }
+                _ => return Err(invalid("invalid literal/length code")),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        // 还没有计入校验和的输出从这里开始
+        let mut unchecked = 0;
+        while n < buf.len() {
+            match std::mem::replace(&mut self.state, State::Done) {
+                State::Header => self.header()?,
+                // 最后一个块结束之后, 读取并检查 gzip / zlib 的尾部
+                State::Block { last: true } => {
+                    self.checksum = update_checksum(self.format, self.checksum, &buf[unchecked..n]);
+                    unchecked = n;
+                    self.trailer()?;
+                }
+                State::Block { last: false } => self.block()?,
+                State::Stored {
+                    mut remaining,
+                    last,
+                } => {
+                    while remaining > 0 && n < buf.len() {
+                        let byte = self.bits.byte()?;
+                        self.push(byte, buf, &mut n);
+                        remaining -= 1;
+                    }
+                    self.state = if remaining == 0 {
+                        State::Block { last }
+                    } else {
+                        State::Stored { remaining, last }
+                    };
+                }
+                State::Codes(mut codes) => {
+                    self.state = if self.codes(&mut codes, buf, &mut n)? {
+                        State::Block { last: codes.last }
+                    } else {
+                        State::Codes(codes)
+                    };
+                }
+                State::Done => break,
+            }
+        }
+        self.checksum = update_checksum(self.format, self.checksum, &buf[unchecked..n]);
+        Ok(n)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid compressed data: {}", msg),
+    )
+}
+
+fn initial_checksum(format: Format) -> u32 {
+    match format {
+        Format::Zlib => 1,
+        // CRC-32 的中间值保存取反之前的结果
+        Format::Gzip | Format::Raw => !0,
+    }
+}
+
+fn update_checksum(format: Format, checksum: u32, bytes: &[u8]) -> u32 {
+    match format {
+        Format::Raw => checksum,
+        Format::Zlib => adler32(checksum, bytes),
+        Format::Gzip => crc32(checksum, bytes),
+    }
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &b in bytes {
+        crc = CRC_TABLE[((crc ^ u32::from(b)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+fn adler32(adler: u32, bytes: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    // 每 5552 个字节取一次模, 这是保证 b 不溢出的最大值
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+// 按 deflate 的顺序 (从每个字节的最低位开始) 读取比特
+struct Bits<R: BufRead> {
+    inner: R,
+    buf: u64,
+    count: u32,
+}
+
+impl<R: BufRead> Bits<R> {
+    fn new(inner: R) -> Bits<R> {
+        Bits {
+            inner,
+            buf: 0,
+            count: 0,
+        }
+    }
+
+    // 尽量把 buf 填到 56 位以上, 读到文件结尾时可能不满
+    fn refill(&mut self) -> io::Result<()> {
+        while self.count <= 56 {
+            let available = self.inner.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            let take = available.len().min(((64 - self.count) / 8) as usize);
+            for &byte in &available[..take] {
+                self.buf |= u64::from(byte) << self.count;
+                self.count += 8;
+            }
+            self.inner.consume(take);
+        }
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        if n == 0 {
+            return Ok(0);
+        }
+        if self.count < n {
+            self.refill()?;
+            if self.count < n {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "compressed data ended unexpectedly",
+                ));
+            }
+        }
+        let value = (self.buf & ((1u64 << n) - 1)) as u32;
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bits(8)? as u8)
+    }
+
+    // 丢弃到下一个字节边界为止的比特
+    fn align(&mut self) {
+        let drop = self.count % 8;
+        self.buf >>= drop;
+        self.count -= drop;
+    }
+
+    // 对齐之后, 查看接下来的至少两个字节 (不足时返回剩下的全部), 不消耗它们
+    fn peek_bytes(&mut self) -> io::Result<Vec<u8>> {
+        self.refill()?;
+        let mut bytes: Vec<u8> = (0..self.count / 8)
+            .map(|i| (self.buf >> (i * 8)) as u8)
+            .collect();
+        if bytes.len() < 2 {
+            bytes.extend_from_slice(self.inner.fill_buf()?);
+        }
+        Ok(bytes)
+    }
+}
+
+// 规范 Huffman 编码的查找表, 以接下来的 max_len 位为下标, 得到 (符号, 编码长度), 长度为 0 表示无效的编码
+struct Huffman {
+    table: Vec<(u16, u8)>,
+    max_len: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let max_len = u32::from(lengths.iter().copied().max().unwrap_or(0));
+        let mut count = [0u16; 16];
+        for &len in lengths {
+            count[usize::from(len)] += 1;
+        }
+        count[0] = 0;
+        // 每种长度的第一个编码
+        let mut next = [0u32; 16];
+        let mut code = 0u32;
+        for len in 1..16 {
+            code = (code + u32::from(count[len - 1])) << 1;
+            next[len] = code;
+            if code + u32::from(count[len]) > 1 << len {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+        let mut table = vec![(0u16, 0u8); 1 << max_len];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let code = next[usize::from(len)];
+            next[usize::from(len)] += 1;
+            // Huffman 编码从最高位开始存放, 而比特是从最低位开始读的, 所以下标要反转
+            let reversed = code.reverse_bits() >> (32 - u32::from(len));
+            let mut index = reversed as usize;
+            while index < table.len() {
+                table[index] = (symbol as u16, len);
+                index += 1 << len;
+            }
+        }
+        Ok(Huffman { table, max_len })
+    }
+
+    fn fixed_literal() -> Huffman {
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        Huffman::new(&lengths).expect("fixed literal code is valid")
+    }
+
+    fn fixed_distance() -> Huffman {
+        Huffman::new(&[5u8; 30]).expect("fixed distance code is valid")
+    }
+
+    fn decode<R: BufRead>(&self, bits: &mut Bits<R>) -> io::Result<u16> {
+        if bits.count < self.max_len {
+            bits.refill()?;
+        }
+        // 文件结尾处不足 max_len 位时用 0 补齐, 查到的编码长度仍然不能超过实际剩下的位数
+        let index = (bits.buf & ((1u64 << self.max_len) - 1)) as usize;
+        let (symbol, len) = self.table[index];
+        if len == 0 {
+            return Err(invalid("invalid Huffman code"));
+        }
+        bits.bits(u32::from(len))?;
+        Ok(symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn decode(bytes: &[u8], format: Format) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        Decoder::new(bytes, format).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    const TEXT: &[u8] = b"Rust:\nsafe, fast, productive.\nPick three.\n";
+
+    #[test]
+    fn test_blocks() {
+        // 固定 Huffman 编码的块
+        let fixed = hex(
+            "0b2a2d2eb1e22a4e4c4bd551484b2c2ed1512828ca4f294d2ec92c4bd5e30ac84cce5628c9284a05b201",
+        );
+        assert_eq!(decode(&fixed, Format::Raw).unwrap(), TEXT);
+        // 不压缩的块
+        let stored = hex("012a00d5ff527573743a0a736166652c20666173742c2070726f647563746976652e0a5069636b2074687265652e0a");
+        assert_eq!(decode(&stored, Format::Raw).unwrap(), TEXT);
+        let zlib = hex("789c0b2a2d2eb1e22a4e4c4bd551484b2c2ed1512828ca4f294d2ec92c4bd5e30ac84cce5628c9284a05b20139230e4c");
+        assert_eq!(detect(&zlib), Some(Format::Zlib));
+        assert_eq!(decode(&zlib, Format::Zlib).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn test_dynamic_block_streaming() {
+        let expected: String = (0..40)
+            .map(|i| {
+                format!(
+                    "line {}: the quick brown fox jumps over the lazy dog {}\n",
+                    i,
+                    i * i % 97
+                )
+            })
+            .collect();
+        let compressed = hex(concat!(
+            "95d55952c3301045d1ffac424b88ba2d5962370c060226868430ad9e02bd5ec0fd76ddd27424af87e392f657e9fd",
+            "71496f97c3ed73ba396d9fc774bf7da5a7cbcbeb396d1fcbe9fff37afdf39deeb687b4dfad7f5566551e95b16a1a",
+            "95b3aa8f6a8233ac232b2cb332b2ca32d76833dc10adadb1ac6a233bcb9a4e2d4322ae0c1a31cd325325b33ae864",
+            "3675504a64508ac76e422a35c68356ba646688c5e3f8a096592f83412d59eb33c8a5689e06b9745d3d835c26f134",
+            "c8a5c7db07bd4cb13ee8a5c73ca19712e707bd3465904b154f875c4cb7dd2197a6793ae452e22704b998583be4d2",
+            "f4c43be452c5cce99f28ce0172c9311ee5a2ebe7d44bddfd02",
+        ));
+        assert_eq!(
+            decode(&compressed, Format::Raw).unwrap(),
+            expected.as_bytes()
+        );
+        // 每次只读几个字节, 长度和距离的复制会跨越多次 read
+        let mut decoder = Decoder::new(&compressed[..], Format::Raw);
+        let mut out = Vec::new();
+        let mut buf = [0u8; 7];
+        loop {
+            let n = decoder.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(out, expected.as_bytes());
+        // 数据被截断时报错, 而不是当成正常结束
+        let err = decode(&compressed[..100], Format::Raw).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_gzip_members() {
+        // 带文件名的成员, 后面接着第二个成员
+        let mut gzip = hex("1f8b08080000000002ff706f656d2e74787400cbcf4be502009fa817f804000000");
+        gzip.extend(hex("1f8b08000000000002032b29cfe702007408179604000000"));
+        assert_eq!(detect(&gzip), Some(Format::Gzip));
+        assert_eq!(decode(&gzip, Format::Gzip).unwrap(), b"one\ntwo\n");
+        // 校验和不对
+        let mut corrupt = hex("1f8b08000000000002032b29cfe702007408179604000000");
+        corrupt[16] ^= 1;
+        let err = decode(&corrupt, Format::Gzip).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(detect(b"x^2 + 1"), None);
+        assert_eq!(detect(b"plain"), None);
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(
+            !crc32(!0, b"The quick brown fox jumps over the lazy dog"),
+            0x414f_a339
+        );
+        assert_eq!(adler32(1, b"Wikipedia"), 0x11e6_0398);
+    }
+}
@@ -0,0 +1,521 @@
+//! `--interactive` 的终端界面: 一边输入查询字符串一边刷新匹配的行, 回车输出选中的 `path:line`
+//!
+//! 开始前先把所有要搜索的文件读进内存, 之后每次按键都用当前的查询字符串重新创建匹配器, 在内存中重新搜索,
+//! 最多保留 [`MAX_RESULTS`] 行. 匹配方式和命令行一样由 [`Config`] 决定, `--fuzzy` 时会搜索所有行,
+//! 保留编辑距离最小的 [`MAX_RESULTS`] 行并把距离小的排在前面.
+//!
+//! 界面画在 `/dev/tty` 上而不是标准输出, 所以可以把结果交给其他命令, 例如 `vim $(mini_grep --interactive src/)`,
+//! 标准输入也可以照常是要搜索的内容. 没有引入依赖, 通过 `stty` 切换终端的 raw 模式,
+//! 用 ANSI 转义序列在备用屏幕上绘制, 退出 (包括出错) 时恢复终端原来的设置.
+
+use std::collections::BinaryHeap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::Error;
+use crate::matcher::Matcher;
+use crate::printer;
+use crate::walk::{self, WalkOptions};
+use crate::{config::BinaryFiles, report, Config, LineMatcher, MatchMode, STDIN_PATH};
+
+/// 最多保留的匹配行数. 没有分数时超过后不再继续搜索, 保证每次按键都能很快刷新
+pub const MAX_RESULTS: usize = 1000;
+
+/// 终端的一次按键, 由 [`keys`] 从读到的字节中解析
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// 输入一个字符
+    Char(char),
+    /// 删除最后一个字符
+    Backspace,
+    /// Ctrl-U: 清空查询字符串
+    Clear,
+    /// 上箭头或 Ctrl-P
+    Up,
+    /// 下箭头或 Ctrl-N
+    Down,
+    /// 回车: 选中当前行并退出
+    Enter,
+    /// Esc, Ctrl-C 或 Ctrl-D: 不选择任何行直接退出
+    Cancel,
+}
+
+/// 解析 raw 模式下读到的字节, 返回按键和用掉的字节数.
+/// 结尾不完整的 UTF-8 字符不会用掉, 留给下一次读到的字节. 不认识的转义序列和控制字符被忽略
+pub fn keys(bytes: &[u8]) -> (Vec<Key>, usize) {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let key = match bytes[i] {
+            // 单独的 Esc 是取消, 后面跟着 `[` 或 `O` 是方向键等的转义序列
+            0x1b => match bytes.get(i + 1) {
+                Some(b'[' | b'O') => {
+                    // 转义序列以 0x40..=0x7e 中的字节结尾
+                    let end = bytes[i + 2..]
+                        .iter()
+                        .position(|b| (0x40..=0x7e).contains(b))
+                        .map_or(bytes.len(), |p| i + 2 + p + 1);
+                    let key = match &bytes[i + 2..end] {
+                        b"A" => Some(Key::Up),
+                        b"B" => Some(Key::Down),
+                        _ => None,
+                    };
+                    keys.extend(key);
+                    i = end;
+                    continue;
+                }
+                _ => Some(Key::Cancel),
+            },
+            0x03 | 0x04 => Some(Key::Cancel),
+            b'\r' | b'\n' => Some(Key::Enter),
+            0x7f | 0x08 => Some(Key::Backspace),
+            0x15 => Some(Key::Clear),
+            0x10 => Some(Key::Up),
+            0x0e => Some(Key::Down),
+            b if b < 0x20 => None,
+            b => {
+                // 一个 UTF-8 字符的长度由第一个字节决定
+                let len = match b {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                };
+                if i + len > bytes.len() {
+                    break;
+                }
+                if let Ok(s) = std::str::from_utf8(&bytes[i..i + len]) {
+                    keys.extend(s.chars().map(Key::Char));
+                }
+                i += len;
+                continue;
+            }
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    (keys, i)
+}
+
+/// 一个读进内存的文件, name 是输出时使用的路径
+pub struct Document {
+    pub name: String,
+    pub text: String,
+}
+
+/// 一个匹配的行, document 是它在 documents 中的下标. 按照在文件中的顺序比较
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Hit {
+    pub document: usize,
+    pub line_number: usize,
+    pub line: String,
+    /// 第一个匹配在 line 中的字节区间, -v 时没有
+    pub span: Option<(usize, usize)>,
+}
+
+/// 界面的状态: 查询字符串, 当前的匹配行和选中的行. 不涉及终端, 可以直接测试
+pub struct State {
+    conf: Config,
+    pub query: String,
+    pub hits: Vec<Hit>,
+    /// 查询字符串不合法时 (例如输入到一半的正则) 的错误, 这时保留上一次的结果
+    pub error: Option<String>,
+    pub selected: usize,
+    // 列表滚动到的位置, 保证选中的行可见
+    top: usize,
+}
+
+impl State {
+    pub fn new(conf: &Config) -> State {
+        State {
+            conf: Config {
+                patterns: None,
+                ..conf.clone()
+            },
+            query: String::new(),
+            hits: Vec::new(),
+            error: None,
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    /// 处理一次按键, 查询字符串变化时重新搜索. 回车时返回 Some(选中的行), 取消时返回 Some(None)
+    pub fn handle(&mut self, key: Key, documents: &[Document]) -> Option<Option<String>> {
+        match key {
+            Key::Char(c) => self.query.push(c),
+            Key::Backspace => {
+                self.query.pop()?;
+            }
+            Key::Clear => self.query.clear(),
+            Key::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                return None;
+            }
+            Key::Down => {
+                self.selected = (self.selected + 1).min(self.hits.len().saturating_sub(1));
+                return None;
+            }
+            Key::Enter => return Some(self.selection(documents)),
+            Key::Cancel => return Some(None),
+        }
+        self.search(documents);
+        None
+    }
+
+    /// 选中的行, 格式是 `path:line`
+    pub fn selection(&self, documents: &[Document]) -> Option<String> {
+        let hit = self.hits.get(self.selected)?;
+        Some(format!(
+            "{}:{}",
+            documents[hit.document].name, hit.line_number
+        ))
+    }
+
+    // 用当前的查询字符串重新搜索所有文件. 查询字符串为空时不显示任何行, 否则每一行都会匹配
+    fn search(&mut self, documents: &[Document]) {
+        self.selected = 0;
+        self.top = 0;
+        if self.query.is_empty() {
+            self.hits.clear();
+            self.error = None;
+            return;
+        }
+        self.conf.query.clone_from(&self.query);
+        let matcher = match LineMatcher::new(&self.conf) {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                self.error = Some(err.to_string());
+                return;
+            }
+        };
+        self.error = None;
+        // 只有模糊匹配的分数会变化, 这时要看完所有行才知道最好的是哪些.
+        // 大顶堆的堆顶是分数最大, 分数相同时最靠后的行, 超过上限时把它去掉
+        let ranked = matches!(self.conf.mode, MatchMode::Fuzzy(_)) && !self.conf.invert;
+        let mut scored = BinaryHeap::new();
+        'documents: for (document, doc) in documents.iter().enumerate() {
+            for (i, (_, line)) in printer::lines(&doc.text).enumerate() {
+                let span = matcher.find(line);
+                if span.is_some() == self.conf.invert {
+                    continue;
+                }
+                let found: Vec<_> = span.into_iter().collect();
+                let hit = Hit {
+                    document,
+                    line_number: i + 1,
+                    line: line.to_string(),
+                    span,
+                };
+                scored.push((matcher.score(line, &found), hit));
+                if scored.len() > MAX_RESULTS {
+                    scored.pop();
+                } else if scored.len() == MAX_RESULTS && !ranked {
+                    break 'documents;
+                }
+            }
+        }
+        // 分数相同的行保持文件中的顺序
+        self.hits = scored
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(_, hit)| hit)
+            .collect();
+    }
+
+    /// 把整个界面画到 out 中, 终端大小是 rows 行 cols 列.
+    /// 第一行是输入的查询字符串, 第二行是匹配数量或错误, 剩下的是匹配的行, 选中的行反色显示
+    pub fn render(&mut self, documents: &[Document], rows: usize, cols: usize, out: &mut Vec<u8>) {
+        let height = rows.saturating_sub(2).max(1);
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + height {
+            self.top = self.selected + 1 - height;
+        }
+        out.extend_from_slice(b"\x1b[H\x1b[2J");
+        let prompt = format!("> {}", self.query);
+        out.extend(truncate(&prompt, cols).bytes());
+        let status = match &self.error {
+            Some(err) => err.clone(),
+            None if self.hits.len() >= MAX_RESULTS => format!("{}+ matches", MAX_RESULTS),
+            None => format!("{} matches", self.hits.len()),
+        };
+        out.extend(format!("\x1b[2;1H\x1b[2m{}\x1b[m", truncate(&status, cols)).bytes());
+        for (row, hit) in self.hits.iter().enumerate().skip(self.top).take(height) {
+            out.extend(format!("\x1b[{};1H", row - self.top + 3).bytes());
+            let selected = row == self.selected;
+            if selected {
+                out.extend_from_slice(b"\x1b[7m");
+            }
+            let prefix = format!("{}:{}:", documents[hit.document].name, hit.line_number);
+            let prefix = truncate(&prefix, cols);
+            out.extend(prefix.bytes());
+            let width = prefix.chars().count()..;
+            for (width, (i, c)) in width.zip(hit.line.char_indices()) {
+                if width >= cols {
+                    break;
+                }
+                match hit.span {
+                    Some((s, e)) if i == s && s < e => out.extend_from_slice(b"\x1b[1;31m"),
+                    Some((_, e)) if i == e => out.extend_from_slice(b"\x1b[22;39m"),
+                    _ => {}
+                }
+                // 制表符和其他控制字符会打乱布局, 用空格代替
+                let c = if c.is_control() { ' ' } else { c };
+                let mut buf = [0; 4];
+                out.extend(c.encode_utf8(&mut buf).bytes());
+            }
+            if selected || hit.span.is_some() {
+                out.extend_from_slice(b"\x1b[m");
+            }
+        }
+        // 光标停在查询字符串的末尾
+        let column = prompt.chars().count().min(cols.saturating_sub(1)) + 1;
+        out.extend(format!("\x1b[1;{}H", column).bytes());
+    }
+}
+
+// 按字符截断到最多 cols 个字符
+fn truncate(s: &str, cols: usize) -> &str {
+    match s.char_indices().nth(cols) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+/// 读取 conf.path 指定的所有文件, 和搜索时一样遍历目录, 跳过被忽略的文件和二进制文件.
+/// 读取失败的文件输出错误后跳过, 只有一个文件时直接返回错误
+pub fn load(conf: &Config) -> Result<Vec<Document>, Error> {
+    if conf.path == STDIN_PATH {
+        let text = read(conf, io::stdin().lock(), Path::new("(standard input)"))?;
+        return Ok(Vec::from_iter(text.map(|text| Document {
+            name: String::from("(standard input)"),
+            text,
+        })));
+    }
+    let root = Path::new(&conf.path);
+    let (files, errors) = walk::files(root, &WalkOptions::from_config(conf)?);
+    errors.iter().for_each(report);
+    let mut documents = Vec::new();
+    for path in files {
+        let text = File::open(&path)
+            .map_err(|e| Error::file(&path, e))
+            .and_then(|file| read(conf, BufReader::new(file), &path));
+        match text {
+            Ok(text) => documents.extend(text.map(|text| Document {
+                name: path.display().to_string(),
+                text,
+            })),
+            Err(err) if !root.is_dir() => return Err(err),
+            Err(err) => report(&err),
+        }
+    }
+    Ok(documents)
+}
+
+// 读取一个输入的全部内容, 不是 UTF-8 的部分用替换字符代替. 除非 --binary-files=text, 否则二进制输入返回 None
+fn read<R: BufRead>(conf: &Config, reader: R, path: &Path) -> Result<Option<String>, Error> {
+    let mut reader: Box<dyn BufRead> = if conf.decompress {
+        crate::inflate::auto(reader).map_err(|e| Error::file(path, e))?
+    } else {
+        Box::new(reader)
+    };
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|e| Error::file(path, e))?;
+    let head = &bytes[..bytes.len().min(walk::BINARY_SNIFF_LEN)];
+    if walk::is_binary(head) && conf.binary_files != BinaryFiles::Text {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+// 终端的 raw 模式和备用屏幕, drop 时恢复原来的设置
+struct Terminal {
+    tty: File,
+    saved: String,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let saved = stty(&tty, &["-g"])?;
+        // min 0 time 1: read 最多等待 0.1 秒, 这样才能区分单独的 Esc 和方向键的转义序列
+        stty(&tty, &["raw", "-echo", "min", "0", "time", "1"])?;
+        let mut terminal = Terminal { tty, saved };
+        terminal.tty.write_all(b"\x1b[?1049h")?;
+        Ok(terminal)
+    }
+
+    // 终端的行数和列数, stty 不支持时使用 24x80
+    fn size(&self) -> (usize, usize) {
+        let size = stty(&self.tty, &["size"]).ok().and_then(|size| {
+            let (rows, cols) = size.split_once(' ')?;
+            Some((rows.parse().ok()?, cols.parse().ok()?))
+        });
+        match size {
+            Some((rows, cols)) if rows > 0 && cols > 0 => (rows, cols),
+            _ => (24, 80),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(b"\x1b[?1049l");
+        let _ = stty(&self.tty, &[&self.saved]);
+    }
+}
+
+// 以 tty 作为标准输入执行 stty, 返回去掉首尾空白的输出
+fn stty(tty: &File, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(tty.try_clone()?)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("stty: {}", stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// 打开交互式界面, 返回选中的 `path:line`, 取消时返回 None
+pub fn run(conf: &Config) -> Result<Option<String>, Error> {
+    // 先读取所有文件, 这样错误可以正常输出到终端上
+    let documents = load(conf)?;
+    let mut terminal = Terminal::open().map_err(|e| Error::file("/dev/tty", e))?;
+    let (rows, cols) = terminal.size();
+    let mut state = State::new(conf);
+    let mut screen = Vec::new();
+    let mut input = Vec::new();
+    let mut buf = [0; 64];
+    loop {
+        screen.clear();
+        state.render(&documents, rows, cols, &mut screen);
+        terminal
+            .tty
+            .write_all(&screen)
+            .map_err(|e| Error::file("/dev/tty", e))?;
+        // 读到 0 个字节说明等待超时, 继续等待
+        let n = terminal
+            .tty
+            .read(&mut buf)
+            .map_err(|e| Error::file("/dev/tty", e))?;
+        input.extend_from_slice(&buf[..n]);
+        let (keys, used) = keys(&input);
+        input.drain(..used);
+        for key in keys {
+            if let Some(selection) = state.handle(key, &documents) {
+                return Ok(selection);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        let (parsed, used) = keys(b"ab\x1b[A\x1b[B\x7f\x15\r");
+        assert_eq!(used, 11);
+        assert_eq!(
+            parsed,
+            [
+                Key::Char('a'),
+                Key::Char('b'),
+                Key::Up,
+                Key::Down,
+                Key::Backspace,
+                Key::Clear,
+                Key::Enter
+            ]
+        );
+        // 单独的 Esc 是取消, 不认识的转义序列被忽略
+        assert_eq!(keys(b"\x1b[5~\x1b").0, [Key::Cancel]);
+        // 不完整的 UTF-8 字符留到下一次
+        assert_eq!(keys("中".as_bytes()).0, [Key::Char('中')]);
+        assert_eq!(keys(&"x中".as_bytes()[..2]), (vec![Key::Char('x')], 1));
+    }
+
+    #[test]
+    fn test_fuzzy_keeps_best() {
+        // 前 MAX_RESULTS 行都和查询差一个字符, 最后一行完全相同, 也要排在最前面
+        let documents = vec![Document {
+            name: String::from("a.txt"),
+            text: "frag\n".repeat(MAX_RESULTS) + "frog\n",
+        }];
+        let mut state = State::new(&Config {
+            path: String::from("."),
+            mode: MatchMode::Fuzzy(1),
+            ..Config::default()
+        });
+        for c in "frog".chars() {
+            state.handle(Key::Char(c), &documents);
+        }
+        assert_eq!(state.hits.len(), MAX_RESULTS);
+        assert_eq!(state.hits[0].line, "frog");
+        assert_eq!(state.hits[0].line_number, MAX_RESULTS + 1);
+        assert_eq!(state.hits[1].line_number, 1);
+    }
+
+    #[test]
+    fn test_search_and_select() {
+        let documents = vec![
+            Document {
+                name: String::from("a.txt"),
+                text: String::from("frog\nbog\n"),
+            },
+            Document {
+                name: String::from("b.txt"),
+                text: String::from("a frog's life\n"),
+            },
+        ];
+        let conf = Config {
+            path: String::from("."),
+            ..Config::default()
+        };
+        let mut state = State::new(&conf);
+        for c in "og".chars() {
+            assert_eq!(state.handle(Key::Char(c), &documents), None);
+        }
+        assert_eq!(state.hits.len(), 3);
+        state.handle(Key::Char('\''), &documents);
+        assert_eq!(state.hits.len(), 1);
+        assert_eq!(state.hits[0].span, Some((4, 7)));
+        // 选中的行不会超出结果的范围
+        state.handle(Key::Backspace, &documents);
+        for _ in 0..5 {
+            state.handle(Key::Down, &documents);
+        }
+        assert_eq!(state.selected, 2);
+        let mut screen = Vec::new();
+        state.render(&documents, 4, 12, &mut screen);
+        let screen = String::from_utf8(screen).unwrap();
+        // 只有两行可以显示匹配, 滚动到选中的最后一行, 每一行都被截断到 12 列
+        assert!(screen.contains("3 matches"));
+        assert!(!screen.contains("a.txt:1:"));
+        assert!(screen.contains("a.txt:2:b\x1b[1;31mog\x1b[m"));
+        assert!(screen.contains("\x1b[7mb.txt:1:a fr\x1b[m"));
+        state.handle(Key::Up, &documents);
+        assert_eq!(
+            state.handle(Key::Enter, &documents),
+            Some(Some(String::from("a.txt:2")))
+        );
+        // 不合法的正则保留上一次的结果
+        let mut state = State::new(&Config {
+            mode: MatchMode::Regex,
+            ..conf
+        });
+        state.handle(Key::Char('b'), &documents);
+        state.handle(Key::Char('('), &documents);
+        assert!(state.error.is_some());
+        assert_eq!(state.hits.len(), 1);
+        assert_eq!(state.handle(Key::Cancel, &documents), Some(None));
+    }
+}
@@ -0,0 +1,162 @@
+//! `--json` 的输出格式
+//!
+//! 每个事件是一行 JSON 对象 (JSON Lines), 用 `type` 字段区分. 字段只会增加, 不会改名或删除,
+//! 所以使用者应该忽略不认识的字段和事件类型.
+//!
+//! - `begin`: 一个文件的第一个 match 或 context 事件之前输出, 没有任何输出的文件不会有 begin 和 end
+//!   `{"type":"begin","path":"src/lib.rs"}`
+//! - `match`: 被选中的行 (-v 时是不匹配的行, submatches 为空)
+//!   `{"type":"match","path":"src/lib.rs","line_number":3,"absolute_offset":42,"text":"let fast = 1;","submatches":[{"match":"fast","start":4,"end":8}]}`
+//! - `context`: -A / -B / -C 要求的上下文行, 字段和 match 相同, submatches 总是空的
+//! - `end`: 一个文件结束, matched_lines 是这个文件被选中的行数
+//!   `{"type":"end","path":"src/lib.rs","matched_lines":1}`
+//! - `summary`: 整个搜索结束时输出一次
+//!   `{"type":"summary","files":12,"files_with_matches":1,"matched_lines":1}`
+//! - `stats`: 传入 --stats 时在 summary 之后输出一次, 字段和 [`crate::SearchStats`] 相同, elapsed 以秒为单位
+//!   `{"type":"stats","files_searched":12,"files_matched":1,"matched_lines":1,"bytes_scanned":4096,"elapsed":0.001200}`
+//!
+//! 说明:
+//! - path 是文件路径, 从标准输入读取时是 `(standard input)`
+//! - line_number 从 1 开始; absolute_offset 是行首在文件中的字节偏移
+//! - text 是去掉换行符的整行, 不是合法 UTF-8 的字节被替换成 U+FFFD
+//! - submatches 中的 start / end 是匹配在 text 中的字节区间 `[start, end)`, match 是匹配的文本
+
+use std::io::{Result, Write};
+
+use crate::SearchStats;
+
+pub(crate) fn begin(out: &mut impl Write, path: &str) -> Result<()> {
+    write!(out, r#"{{"type":"begin","path":"#)?;
+    string(out, path)?;
+    writeln!(out, "}}")
+}
+
+/// kind 为 "match" 或 "context"
+pub(crate) fn line(
+    out: &mut impl Write,
+    kind: &str,
+    path: &str,
+    number: usize,
+    offset: usize,
+    text: &str,
+    found: &[(usize, usize)],
+) -> Result<()> {
+    write!(out, r#"{{"type":"{}","path":"#, kind)?;
+    string(out, path)?;
+    write!(
+        out,
+        r#","line_number":{},"absolute_offset":{},"text":"#,
+        number, offset
+    )?;
+    string(out, text)?;
+    write!(out, r#","submatches":["#)?;
+    for (i, &(start, end)) in found.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, r#"{{"match":"#)?;
+        string(out, &text[start..end])?;
+        write!(out, r#","start":{},"end":{}}}"#, start, end)?;
+    }
+    writeln!(out, "]}}")
+}
+
+pub(crate) fn end(out: &mut impl Write, path: &str, matched_lines: usize) -> Result<()> {
+    write!(out, r#"{{"type":"end","path":"#)?;
+    string(out, path)?;
+    writeln!(out, r#","matched_lines":{}}}"#, matched_lines)
+}
+
+pub(crate) fn summary(
+    out: &mut impl Write,
+    files: usize,
+    files_with_matches: usize,
+    matched_lines: usize,
+) -> Result<()> {
+    writeln!(
+        out,
+        r#"{{"type":"summary","files":{},"files_with_matches":{},"matched_lines":{}}}"#,
+        files, files_with_matches, matched_lines
+    )
+}
+
+/// 输出 stats 事件. 统计信息在 [`crate::run`] 返回之后才有, 所以由调用者输出
+pub fn stats(out: &mut impl Write, stats: &SearchStats) -> Result<()> {
+    writeln!(
+        out,
+        r#"{{"type":"stats","files_searched":{},"files_matched":{},"matched_lines":{},"bytes_scanned":{},"elapsed":{:.6}}}"#,
+        stats.files_searched,
+        stats.files_matched,
+        stats.matched_lines,
+        stats.bytes_scanned,
+        stats.elapsed.as_secs_f64()
+    )
+}
+
+// 输出带引号的 JSON 字符串, 只转义 JSON 要求转义的字符, 其他字符原样输出
+fn string(out: &mut impl Write, s: &str) -> Result<()> {
+    write!(out, "\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            c if c < ' ' => "",
+            _ => continue,
+        };
+        write!(out, "{}", &s[start..i])?;
+        if escaped.is_empty() {
+            write!(out, "\\u{:04x}", c as u32)?;
+        } else {
+            write!(out, "{}", escaped)?;
+        }
+        start = i + c.len_utf8();
+    }
+    write!(out, "{}\"", &s[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_escape() {
+        let mut out = Vec::new();
+        string(&mut out, "a\"b\\c\n\t\u{1}é").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#""a\"b\\c\n\t\u0001é""#);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut out = Vec::new();
+        let searched = SearchStats {
+            files_searched: 2,
+            files_matched: 1,
+            matched_lines: 3,
+            bytes_scanned: 39,
+            elapsed: std::time::Duration::from_millis(5),
+        };
+        stats(&mut out, &searched).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"type":"stats","files_searched":2,"files_matched":1,"matched_lines":3,"bytes_scanned":39,"elapsed":0.005000}"#
+                .to_string()
+                + "\n"
+        );
+    }
+
+    #[test]
+    fn test_line() {
+        let mut out = Vec::new();
+        line(&mut out, "match", "a.txt", 3, 42, "let fast", &[(4, 8)]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"type":"match","path":"a.txt","line_number":3,"absolute_offset":42,"text":"let fast","submatches":[{"match":"fast","start":4,"end":8}]}"#
+                .to_string()
+                + "\n"
+        );
+    }
+}
@@ -3,104 +3,575 @@
 //! ## Usage Example
 //! ```bash
 //! IGNORE_CASE=1 cargo run -- searchstring example-filename.txt
+//! MINI_GREP_CONFIG=~/.mini_grep cargo run -- --debug-config -s nobody poem.txt
+//! cargo run -- --regex "^(How|Then) \w+" poem.txt
+//! cargo run -- --hidden --binary -n searchstring src/
+//! cargo run -- -n --column -C 1 nobody poem.txt
+//! cat poem.txt | cargo run -- nobody
+//! cargo run -- -j 8 -n fn src/
+//! cargo run -- -e nobody -e somebody -f blocklist.txt poem.txt
+//! cargo run -- -l -i frog src/ && cargo run -- -q frog poem.txt
+//! MINI_GREP_COLORS='ms=01;32' cargo run -- --color=always -n nobody poem.txt | less -R
+//! cargo run -- --json -C 1 nobody poem.txt
+//! cargo run -- -t rust --exclude 'tests/**' -n unwrap ../
+//! cargo run -- -w -i duct poem.txt && cargo run -- -x 'Pick three.' poem.txt
+//! cargo run -- --replace '[$0]' -n nobody poem.txt
+//! cargo run -- --regex 'colou?r' --replace color --in-place --backup .bak src/
+//! cargo run -- -z -c ERROR /var/log/syslog.2.gz
+//! cargo run -- --fuzzy 2 --sort=score -n receive poem.txt
+//! cargo run -- --sort=matches --stats -c unwrap ../
+//! cargo run -- index ../ && cargo run -- --index -n unwrap ../
+//! cargo run --features mmap -- --binary-files=without-match -c ERROR /var/log/
+//! cargo run -- -U -n --regex 'fn \w+\(\n' src/
+//! vim $(cargo run -- --interactive -i src/)
+//! cargo run -- --help
 //! ```
+//!
+//! 作为库使用时, 用 [`build_matcher`] 和 [`searcher::Searcher`] 搜索任意输入, 结果交给自己实现的 [`searcher::Sink`].
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use std::io::{Error, ErrorKind};
-use std::{env, fs};
+pub mod aho_corasick;
+pub mod color;
+pub mod config;
+pub mod error;
+pub mod fold;
+pub mod fuzzy;
+pub mod glob;
+pub mod ignore;
+pub mod index;
+pub mod inflate;
+pub mod interactive;
+pub mod json;
+pub mod literal;
+pub mod matcher;
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+mod mmap;
+mod parallel;
+pub mod printer;
+pub mod regex;
+pub mod replace;
+pub mod searcher;
+pub mod walk;
 
-pub fn run(conf: Config) -> Result<(), Error> {
-    println!(
-        "ignore case: {}",
-        if conf.ignore_case { "true" } else { "false" }
-    );
-    println!("searching for \"{}\" in \"{}\"\n", conf.query, conf.path);
-    let contents = fs::read_to_string(conf.path)?;
-    let results = search(conf.ignore_case, &conf.query, &contents)?;
-    for line in results {
-        println!("{}", line);
+use aho_corasick::AhoCorasick;
+use config::ColorChoice;
+pub use config::{BinaryFiles, Config, MatchMode, OutputMode, SortBy, USAGE};
+pub use error::Error;
+use fold::CaseInsensitive;
+use fuzzy::Fuzzy;
+use literal::Horspool;
+use matcher::Matcher;
+use printer::Printer;
+use regex::Regex;
+use replace::Replacement;
+use searcher::Searcher;
+use walk::WalkOptions;
+
+/// [`run`] 的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Outcome {
+    /// 是否有被选中的行
+    pub matched: bool,
+    /// 因为无法读取而跳过的文件和目录数量
+    pub errors: usize,
+    /// 搜索的统计信息, 传入 --stats 时由调用者输出. 命令行输出到标准错误, --json 时用 [`json::stats`] 输出到标准输出
+    pub stats: SearchStats,
+}
+
+/// 一次搜索的统计信息
+///
+/// --in-place 时 matched_lines 是被替换的行数. Display 的格式是每项一行, 例如 `3 files searched`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchStats {
+    /// 搜索过的文件数量, 从标准输入读取时是 1, 读取失败的文件不算
+    pub files_searched: usize,
+    /// 有被选中的行的文件数量
+    pub files_matched: usize,
+    /// 被选中的行数之和
+    pub matched_lines: usize,
+    /// 读取的字节数. -z 时是解压之后的字节数; -l / -q 之类提前结束时, 没有读到的部分不算
+    pub bytes_scanned: u64,
+    /// 从开始搜索到结束的时间, 包括遍历目录
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} matched lines", self.matched_lines)?;
+        writeln!(f, "{} files contained matches", self.files_matched)?;
+        writeln!(f, "{} files searched", self.files_searched)?;
+        writeln!(f, "{} bytes searched", self.bytes_scanned)?;
+        write!(f, "{:.6} seconds", self.elapsed.as_secs_f64())
+    }
+}
+
+impl Outcome {
+    /// 和 grep 一样的退出码: 有被选中的行时为 0, 没有时为 1, 有文件读取失败时为 2.
+    /// -q 是例外, 只要有被选中的行就是 0
+    pub fn exit_code(&self, quiet: bool) -> i32 {
+        if self.errors > 0 && !(quiet && self.matched) {
+            2
+        } else if self.matched {
+            0
+        } else {
+            1
+        }
     }
-    Ok(())
 }
 
-/// Config 结构体, 用于存储命令行参数
+/// 按照 conf 搜索并把结果输出到标准输出
 ///
-/// # parameters
-/// 1. query: 查询字符串
-/// 2. path: 文件路径
-/// 3. ignore_case: 是否忽略大小写, 默认为 false, 可以通过环境变量 IGNORE_CASE 设置
-/// `ignore_case 为 1, true, TRUE, True 时为 true, 其他值为 false`
-pub struct Config {
-    query: String,
-    path: String,
-    ignore_case: bool,
+/// 搜索目录时, 无法读取的文件和目录会在标准错误中报告, 然后跳过, 数量记录在 [`Outcome::errors`] 中.
+/// 其他错误, 例如参数或模式不合法, 指定的文件不存在, 写输出失败, 会直接返回.
+/// 统计信息在 [`Outcome::stats`] 中返回, 这里不输出
+pub fn run(mut conf: Config) -> Result<Outcome, Error> {
+    let started = Instant::now();
+    // 到这里才知道输出的去向, 所以 --color=auto 在这里决定
+    let stdout = io::stdout().lock();
+    conf.color = if color::enabled(conf.color, stdout.is_terminal()) {
+        ColorChoice::Always
+    } else {
+        ColorChoice::Never
+    };
+    let matcher = LineMatcher::new(&conf)?;
+    let root = Path::new(&conf.path);
+    // 只有搜索目录时才在每一行前面输出文件路径
+    let mut printer = Printer::new(&conf, stdout).with_filename(root.is_dir());
+    if conf.in_place {
+        let mut outcome = rewrite_files(&conf, &matcher, root)?;
+        outcome.stats.elapsed = started.elapsed();
+        return Ok(outcome);
+    }
+    // path 为 `-` 或者没有传 path 时, 从标准输入读取
+    let errors = if conf.path == STDIN_PATH {
+        let name = PathBuf::from(printer.name());
+        search_input(
+            &conf,
+            &matcher,
+            io::stdin().lock(),
+            Some(&name),
+            &mut printer,
+        )?;
+        0
+    } else if conf.use_index && !root.is_dir() {
+        return Err(Error::Args(format!(
+            "option '--index' requires a directory, '{}' is not one",
+            conf.path
+        )));
+    } else if !root.is_dir() {
+        search_file(&conf, &matcher, root, &mut printer)?;
+        0
+    } else {
+        search_dir(&conf, &matcher, root, &mut printer)?
+    };
+    printer.summary()?;
+    Ok(Outcome {
+        matched: printer.total() > 0,
+        errors,
+        stats: SearchStats {
+            elapsed: started.elapsed(),
+            ..printer.stats()
+        },
+    })
+}
+
+// 搜索目录时, 每一行前面都加上文件路径, 和 grep -r 一样. 返回跳过的文件和目录数量
+fn search_dir<W: Write>(
+    conf: &Config,
+    matcher: &LineMatcher,
+    root: &Path,
+    printer: &mut Printer<W>,
+) -> Result<usize, Error> {
+    let opts = WalkOptions::from_config(conf)?;
+    let (mut files, walk_errors) = if conf.use_index {
+        index::files(conf, root, &opts)?
+    } else {
+        walk::files(root, &opts)
+    };
+    walk_errors.iter().for_each(report);
+    match conf.sort {
+        Some(SortBy::Path) => files.sort(),
+        // 读不到修改时间的是 None, 排在最前面
+        Some(SortBy::Modified) => {
+            files.sort_by_cached_key(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        }
+        _ => {}
+    }
+    // -j 为 0 时使用和 CPU 核数相同的线程数. --sort=score 时所有结果都要排序之后再输出, 多线程没有好处
+    let jobs = match conf.jobs {
+        _ if conf.sort == Some(SortBy::Score) => 1,
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        jobs => jobs,
+    };
+    let by_matches = conf.sort == Some(SortBy::Matches);
+    // --sort=matches 时每个文件的结果都要先缓存起来, 和多线程搜索一样, 所以一个线程时也走这条路
+    let errors = if by_matches || (jobs > 1 && files.len() > 1) {
+        parallel::search_files(conf, matcher, &files, jobs, by_matches, printer)?
+    } else {
+        search_files(conf, matcher, &files, printer)?
+    };
+    Ok(walk_errors.len() + errors)
 }
 
-impl Config {
-    pub fn new(args: &[String]) -> Result<Config, Error> {
-        if args.len() != 3 {
-            Err(Error::new(
-                ErrorKind::InvalidInput,
-                "need 2 arguments, [query] [path]",
-            ))?
+// 依次搜索 files, 无法读取的文件报告之后跳过, 返回跳过的文件数量
+fn search_files<W: Write>(
+    conf: &Config,
+    matcher: &LineMatcher,
+    files: &[PathBuf],
+    printer: &mut Printer<W>,
+) -> Result<usize, Error> {
+    let mut errors = 0;
+    for file in files {
+        match search_file(conf, matcher, file, printer) {
+            Ok(_) => {}
+            Err(err @ Error::File { .. }) => {
+                report(&err);
+                errors += 1;
+            }
+            Err(err) => return Err(err),
         }
-        // 跳过 args[0], 因为它是程序名
-        let query = &args[1];
-        let path = &args[2];
-        // env::var 返回一个 Result, 如果环境变量不存在, 则返回 Err
-        let ignore_case = match env::var("IGNORE_CASE") {
-            Ok(val) => val == "1" || val == "true" || val == "TRUE" || val == "True",
-            Err(_) => false,
-        };
-        // query 和 path 的所有权都在 parse_config 中, 所以需要 clone 才能返回
-        Ok(Config {
-            query: query.clone(),
-            path: path.clone(),
-            ignore_case,
+        // -q 时找到一个被选中的行就可以结束了
+        if conf.output == OutputMode::Quiet && printer.total() > 0 {
+            break;
+        }
+    }
+    Ok(errors)
+}
+
+// 打开并搜索 path, 每一行前面是否输出 path 由 printer 决定
+fn search_file<W: Write>(
+    conf: &Config,
+    matcher: &LineMatcher,
+    path: &Path,
+    printer: &mut Printer<W>,
+) -> Result<usize, Error> {
+    let file = File::open(path).map_err(|e| Error::file(path, e))?;
+    // 开启 mmap feature 时, 大文件映射到内存中搜索, 不用经过读缓冲区
+    #[cfg(all(feature = "mmap", unix, target_pointer_width = "64"))]
+    if let Some(map) = mmap::Mmap::open(&file).map_err(|e| Error::file(path, e))? {
+        return search_input(conf, matcher, &map[..], Some(path), printer);
+    }
+    let reader = BufReader::with_capacity(READ_BUFFER_SIZE, file);
+    search_input(conf, matcher, reader, Some(path), printer)
+}
+
+// 交给 Searcher 搜索, printer 作为 Sink 输出结果, 读取的字节数也由 Searcher 告诉 printer
+fn search_input<R: BufRead, W: Write>(
+    conf: &Config,
+    matcher: &LineMatcher,
+    reader: R,
+    path: Option<&Path>,
+    printer: &mut Printer<W>,
+) -> Result<usize, Error> {
+    Searcher::from_config(conf).search(matcher, reader, path, printer)
+}
+
+// --in-place: 把每个文件中的匹配替换掉后原子地写回去, 不输出搜索结果.
+// 无法读取或写入的文件和搜索时一样, 报告之后跳过
+fn rewrite_files(conf: &Config, matcher: &LineMatcher, root: &Path) -> Result<Outcome, Error> {
+    let replace = conf
+        .replace
+        .as_ref()
+        .ok_or_else(|| Error::Args("option '--in-place' requires --replace".to_string()))?;
+    let (files, walk_errors) = if root.is_dir() {
+        walk::files(root, &WalkOptions::from_config(conf)?)
+    } else {
+        (vec![root.to_path_buf()], Vec::new())
+    };
+    walk_errors.iter().for_each(report);
+    let mut outcome = Outcome {
+        errors: walk_errors.len(),
+        ..Outcome::default()
+    };
+    for file in &files {
+        match rewrite_file(matcher, replace, file, conf.backup.as_deref()) {
+            Ok((changed, len)) => {
+                let stats = &mut outcome.stats;
+                stats.files_searched += 1;
+                stats.files_matched += usize::from(changed > 0);
+                stats.matched_lines += changed;
+                stats.bytes_scanned += len as u64;
+                outcome.matched |= changed > 0;
+            }
+            Err(err) => {
+                report(&err);
+                outcome.errors += 1;
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+// 返回被替换的行数和文件的字节数, 没有匹配的文件不会被改写. 换行符原样保留.
+// 和搜索不同, 不是合法 UTF-8 的文件会报错, 而不是把其中的字节替换成 U+FFFD 后写回去
+fn rewrite_file(
+    matcher: &LineMatcher,
+    replace: &Replacement,
+    path: &Path,
+    backup: Option<&str>,
+) -> Result<(usize, usize), Error> {
+    let contents = fs::read_to_string(path).map_err(|e| Error::file(path, e))?;
+    let mut output = String::with_capacity(contents.len());
+    let mut found = Vec::new();
+    let mut changed = 0;
+    for raw in contents.split_inclusive('\n') {
+        let line = match raw.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => raw,
+        };
+        matcher.find_into(line, true, &mut found);
+        if found.is_empty() {
+            output.push_str(raw);
+            continue;
+        }
+        changed += 1;
+        output.push_str(&replace.apply(line, &found).0);
+        output.push_str(&raw[line.len()..]);
+    }
+    if changed > 0 {
+        replace::write_atomic(path, output.as_bytes(), backup).map_err(|e| Error::file(path, e))?;
+    }
+    Ok((changed, contents.len()))
+}
+
+// 报告一个被跳过的文件, 格式和 main 输出错误时一样
+pub(crate) fn report(err: &Error) {
+    eprintln!("mini_grep: {}", err);
+}
+
+/// Config.path 为这个值时从标准输入读取
+pub const STDIN_PATH: &str = "-";
+
+// 每个文件的读缓冲区大小, 内存占用只和它以及最长的一行有关, 和文件大小无关
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 从 reader 中逐行读取并搜索, 结果交给 printer 输出, 返回被选中的行数
+///
+/// 无论文件多大, 同一时间只有一行在内存中. 不是合法 UTF-8 的字节会被替换成 U+FFFD, 而不是让搜索失败.
+/// 和命令行一样, -z 时先解压, 二进制输入按 conf.binary_files 处理
+pub fn search_reader<R: BufRead, W: Write>(
+    conf: &Config,
+    reader: R,
+    path: Option<&Path>,
+    printer: &mut Printer<W>,
+) -> Result<usize, Error> {
+    search_input(conf, &LineMatcher::new(conf)?, reader, path, printer)
+}
+
+/// 一个匹配的行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    /// 行号, 从 1 开始
+    pub line_number: usize,
+    /// 行首在整个文本中的字节偏移
+    pub byte_offset: usize,
+    /// 整行内容, 不包含换行符
+    pub line: &'a str,
+    /// 第一个匹配在行内的字节区间 `[start, end)`
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Match<'_> {
+    /// 匹配到的那部分文本
+    pub fn matched(&self) -> &str {
+        &self.line[self.start..self.end]
+    }
+}
+
+/// 按照 conf 中的查询字符串 (或 -e / -f 指定的多个模式), 匹配模式, 是否忽略大小写以及 -w / -x 创建匹配器,
+/// 和命令行使用的完全一样. 配合 [`searcher::Searcher`] 使用, 也可以直接调用 [`Matcher`] 的方法
+pub fn build_matcher(conf: &Config) -> Result<impl Matcher + Send + Sync, Error> {
+    LineMatcher::new(conf)
+}
+
+/// 按照 conf 中的查询字符串 (或 -e / -f 指定的多个模式), 匹配模式和是否忽略大小写, 找出 contents 中所有匹配的行
+///
+/// 和 search 不同, 返回的 Match 带有行号, 字节偏移和匹配区间. 这里不考虑 conf.invert
+pub fn find_matches<'a>(conf: &Config, contents: &'a str) -> Result<Vec<Match<'a>>, Error> {
+    Ok(LineMatcher::new(conf)?.find_matches(contents))
+}
+
+// 判断一行是否匹配, 查询字符串只在创建时处理一次, 而不是每一行都重新处理
+enum LineMatcher {
+    Literal(Horspool),
+    CaseInsensitive(CaseInsensitive),
+    Regex(Regex),
+    // 多个字面模式, 不管有多少个模式, 每一行都只扫描一遍
+    Multi(AhoCorasick),
+    // -w: 匹配的前后都不能是单词字符
+    Word(Box<LineMatcher>),
+    // -x: 匹配必须是整行
+    WholeLine(Box<LineMatcher>),
+    // --fuzzy: 每个模式一个, 取编辑距离最小的匹配
+    Fuzzy(Vec<Fuzzy>),
+}
+
+impl LineMatcher {
+    fn new(conf: &Config) -> Result<LineMatcher, Error> {
+        let matcher = LineMatcher::with_patterns(conf, conf.queries())?;
+        // -x 优先于 -w, 和 grep 一样. 正则直接加上锚点, 这样 `a|ab` 也能匹配整行 `ab`
+        Ok(if conf.line_regexp && conf.mode != MatchMode::Regex {
+            LineMatcher::WholeLine(Box::new(matcher))
+        } else if conf.word_regexp && !conf.line_regexp {
+            LineMatcher::Word(Box::new(matcher))
+        } else {
+            matcher
+        })
+    }
+
+    fn with_patterns(conf: &Config, patterns: &[String]) -> Result<LineMatcher, Error> {
+        Ok(match (conf.mode, patterns) {
+            // 没有任何模式时 (例如 -f 读到空文件) 不匹配任何行
+            (_, []) => LineMatcher::Multi(AhoCorasick::new(patterns, conf.ignore_case)),
+            (MatchMode::Literal, [query]) if conf.ignore_case => {
+                LineMatcher::CaseInsensitive(CaseInsensitive::new(query))
+            }
+            (MatchMode::Literal, [query]) => LineMatcher::Literal(Horspool::new(query.as_bytes())),
+            // 空模式匹配每一行, 不需要再看其他模式
+            (MatchMode::Literal, _) if patterns.iter().any(String::is_empty) => {
+                LineMatcher::Literal(Horspool::new(b""))
+            }
+            (MatchMode::Literal, _) => {
+                LineMatcher::Multi(AhoCorasick::new(patterns, conf.ignore_case))
+            }
+            (MatchMode::Regex, [query]) if conf.line_regexp => {
+                Regex::new(query)?;
+                LineMatcher::Regex(Regex::with_options(
+                    &format!("^(?:{})$", query),
+                    conf.ignore_case,
+                )?)
+            }
+            // -U 时 ^ 和 $ 匹配每一行的开头和结尾, 和逐行搜索时一样
+            (MatchMode::Regex, [query]) if conf.multiline => {
+                LineMatcher::Regex(Regex::with_multi_line(query, conf.ignore_case)?)
+            }
+            (MatchMode::Regex, [query]) => {
+                LineMatcher::Regex(Regex::with_options(query, conf.ignore_case)?)
+            }
+            // 多个正则合并成一个选择. 先单独编译每一个, 否则 `a)|(b` 这种模式合并后反而是合法的
+            (MatchMode::Regex, _) => {
+                for pattern in patterns {
+                    Regex::new(pattern)?;
+                }
+                let alternation: Vec<String> =
+                    patterns.iter().map(|p| format!("(?:{})", p)).collect();
+                LineMatcher::with_patterns(conf, &[alternation.join("|")])?
+            }
+            (MatchMode::Fuzzy(k), _) => LineMatcher::Fuzzy(
+                patterns
+                    .iter()
+                    .map(|p| Fuzzy::new(p, k, conf.ignore_case))
+                    .collect(),
+            ),
         })
     }
-    // 优化后的 new 方法, 使用迭代器取代性能不好的 clone
-    pub fn iter_new(mut args: impl Iterator<Item = String>) -> Result<Config, Error> {
-        let mut index = 0;
-        let ignore_case = match env::var("IGNORE_CASE") {
-            Ok(val) => val == "1" || val == "true" || val == "TRUE" || val == "True",
-            Err(_) => false,
-        };
-        let mut cfg = Config {
-            query: String::new(),
-            path: String::new(),
-            ignore_case,
-        };
-        while let Some(arg) = args.next() {
-            match index {
-                1 => cfg.query = arg,
-                2 => cfg.path = arg,
-                _ => Err(Error::new(
-                    ErrorKind::InvalidInput,
-                    "need 2 arguments, [query] [path]",
-                ))?,
+
+    // 和 grep -w 一样, 匹配前后的字符都不能是单词字符 (Unicode 字母, 数字和下划线),
+    // 不满足时从匹配开头的下一个字符继续找, 所以 `-w duct` 会跳过 productive 找到后面的 duct
+    fn find_word_at(&self, line: &str, mut start: usize) -> Option<(usize, usize)> {
+        loop {
+            let (s, e) = self.find_at(line, start)?;
+            let before = line[..s].chars().next_back();
+            let after = line[e..].chars().next();
+            if !before.is_some_and(regex::is_word_char) && !after.is_some_and(regex::is_word_char) {
+                return Some((s, e));
+            }
+            start = s + line[s..].chars().next()?.len_utf8();
+        }
+    }
+
+    fn find_matches<'a>(&self, contents: &'a str) -> Vec<Match<'a>> {
+        printer::lines(contents)
+            .enumerate()
+            .filter_map(|(index, (byte_offset, line))| {
+                let (start, end) = self.find(line)?;
+                Some(Match {
+                    line_number: index + 1,
+                    byte_offset,
+                    line,
+                    start,
+                    end,
+                })
+            })
+            .collect()
+    }
+}
+
+// 字面匹配的查找器都交给 Matcher 的实现, 这里只处理多个模糊模式和 -w / -x 的组合
+impl Matcher for LineMatcher {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        match self {
+            LineMatcher::Literal(finder) => Matcher::find_at(finder, line, start),
+            LineMatcher::CaseInsensitive(finder) => Matcher::find_at(finder, line, start),
+            LineMatcher::Multi(finder) => Matcher::find_at(finder, line, start),
+            LineMatcher::Regex(re) => re.find_at(line, start),
+            // 编辑距离最小的优先, 距离相同时取最靠左的
+            LineMatcher::Fuzzy(fuzzies) => fuzzies
+                .iter()
+                .filter_map(|f| f.find(&line[start..]))
+                .min_by_key(|&(s, _, distance)| (distance, s))
+                .map(|(s, e, _)| (start + s, start + e)),
+            LineMatcher::Word(inner) => inner.find_word_at(line, start),
+            LineMatcher::WholeLine(inner) => {
+                let whole = match &**inner {
+                    // 模糊匹配时整行和某个模式的编辑距离不超过 k 就算匹配
+                    LineMatcher::Fuzzy(fuzzies) => {
+                        fuzzies.iter().any(|f| f.distance(line) <= f.max_distance())
+                    }
+                    // 字面模式的最左最长匹配不是整行时, 更短的匹配也不会是
+                    inner => inner.find(line) == Some((0, line.len())),
+                };
+                (start == 0 && whole).then_some((0, line.len()))
+            }
+        }
+    }
+
+    // 模糊匹配时是第一个匹配和所有模式中最接近的那个的编辑距离, 也就是这一行中最小的编辑距离
+    fn score(&self, line: &str, found: &[(usize, usize)]) -> usize {
+        match (self, found.first()) {
+            (LineMatcher::Fuzzy(fuzzies), Some(&(s, e))) => fuzzies
+                .iter()
+                .map(|f| f.distance(&line[s..e]))
+                .min()
+                .unwrap_or(0),
+            (LineMatcher::Word(inner) | LineMatcher::WholeLine(inner), _) => {
+                inner.score(line, found)
             }
-            index += 1;
+            _ => 0,
+        }
+    }
+
+    fn as_literal(&self) -> Option<&Horspool> {
+        match self {
+            LineMatcher::Literal(finder) => Some(finder),
+            _ => None,
         }
-        Ok(cfg)
     }
 }
 
+// 忽略大小写时按 Unicode 大小写折叠比较, 不会为每一行分配一个小写的副本
 pub fn search<'a>(
     ignore_case: bool,
     query: &str,
     contents: &'a str,
 ) -> Result<Vec<&'a str>, Error> {
-    let mut query = query.to_string();
-    if ignore_case {
-        query = query.to_lowercase();
-    }
+    let finder = CaseInsensitive::new(query);
     let mut results = Vec::new();
     for line in contents.lines() {
-        let mut search_line = line.to_string();
-        if ignore_case {
-            search_line = search_line.to_lowercase();
-        }
-        if search_line.contains(&query) {
+        let found = if ignore_case {
+            finder.is_match(line)
+        } else {
+            line.contains(query)
+        };
+        if found {
             results.push(line);
         }
     }
@@ -113,21 +584,37 @@ pub fn filter_search<'a>(
     query: &str,
     contents: &'a str,
 ) -> Result<Vec<&'a str>, Error> {
+    // query 在进入循环之前折叠一次, 而不是每一行都重新处理
+    let finder = CaseInsensitive::new(query);
     let results = contents
         .lines()
         .filter(|line| {
-            let mut search_line = line.to_string();
-            let mut search_query = query.to_string();
             if ignore_case {
-                search_line = search_line.to_lowercase();
-                search_query = search_query.to_lowercase();
+                finder.is_match(line)
+            } else {
+                line.contains(query)
             }
-            search_line.contains(&search_query)
         })
         .collect();
     Ok(results)
 }
 
+// 区分大小写的 search, 不再逐行调用 contains, 而是用 Horspool 在整个文本上查找,
+// 只在命中的位置附近寻找行边界, 结果和 search(false, query, contents) 完全一样
+pub fn fast_search<'a>(query: &str, contents: &'a str) -> Result<Vec<&'a str>, Error> {
+    Ok(Horspool::new(query.as_bytes()).search_lines(contents))
+}
+
+// 正则模式下的 search, 模式只编译一次, 非法的模式会返回 InvalidInput 错误
+pub fn regex_search<'a>(
+    ignore_case: bool,
+    query: &str,
+    contents: &'a str,
+) -> Result<Vec<&'a str>, Error> {
+    let re = Regex::with_options(query, ignore_case)?;
+    Ok(contents.lines().filter(|line| re.is_match(line)).collect())
+}
+
 // 改造为 Config 结构体的 new 方法
 // fn parse_config(args: &[String]) -> Config {
 //     let query = &args[1];
@@ -144,33 +631,507 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_new() {
-        let args = vec![
-            String::from("mini_grep"),
-            String::from("searchstring"),
-            String::from("example-filename.txt"),
-        ];
-        let conf = Config::new(&args).unwrap();
-        assert_eq!(conf.query, "searchstring");
-        assert_eq!(conf.path, "example-filename.txt");
+    fn test_search() {
+        let query = "duct";
+        // \ 是 Rust 的换行符, 表示下一行也是字符串的一部分
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+        let results = search(true, query, contents).unwrap();
+        assert_eq!(results, vec!["safe, fast, productive."]);
+        assert_eq!(filter_search(true, query, contents).unwrap(), results);
+        let contents = "Straße\nStrasse\nStrase";
+        assert_eq!(
+            search(true, "STRASSE", contents).unwrap(),
+            vec!["Straße", "Strasse"]
+        );
+        assert_eq!(
+            filter_search(true, "straße", contents).unwrap(),
+            vec!["Straße", "Strasse"]
+        );
     }
 
     #[test]
-    #[should_panic(expected = "need 2 arguments, [query] [path]")]
-    fn test_new_with_invalid_args() {
-        let args = vec![String::from("mini_grep"), String::from("searchstring")];
-        let _conf = Config::new(&args).unwrap();
+    fn test_find_matches() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+        let conf = Config {
+            query: String::from("FAST"),
+            ignore_case: true,
+            ..Config::default()
+        };
+        let matches = find_matches(&conf, contents).unwrap();
+        assert_eq!(
+            matches,
+            vec![Match {
+                line_number: 2,
+                byte_offset: 6,
+                line: "safe, fast, productive.",
+                start: 6,
+                end: 10,
+            }]
+        );
+        assert_eq!(matches[0].matched(), "fast");
+        // 小写后字节长度会变化的字符 (İ 小写是 i 加上一个组合字符)
+        let conf = Config {
+            query: String::from("x"),
+            ignore_case: true,
+            ..Config::default()
+        };
+        let matches = find_matches(&conf, "İİX").unwrap();
+        assert_eq!((matches[0].start, matches[0].end), (4, 5));
     }
 
     #[test]
-    fn test_search() {
-        let query = "duct";
-        // \ 是 Rust 的换行符, 表示下一行也是字符串的一部分
+    fn test_find_multiple_patterns() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+        let lines = |conf: &Config| -> Vec<&str> {
+            let matches = find_matches(conf, contents).unwrap();
+            matches.iter().map(|m| m.line).collect()
+        };
+        let patterns = |list: &[&str]| Some(list.iter().map(|s| s.to_string()).collect());
+        let conf = Config {
+            patterns: patterns(&["three", "duct"]),
+            ..Config::default()
+        };
+        assert_eq!(lines(&conf), vec!["safe, fast, productive.", "Pick three."]);
+        let conf = Config {
+            ignore_case: true,
+            ..conf
+        };
+        assert_eq!(
+            lines(&conf),
+            vec!["safe, fast, productive.", "Pick three.", "Duct tape."]
+        );
+        let conf = Config {
+            patterns: patterns(&["^D", r"\w+:$"]),
+            mode: MatchMode::Regex,
+            ..Config::default()
+        };
+        assert_eq!(lines(&conf), vec!["Rust:", "Duct tape."]);
+        let conf = Config {
+            patterns: patterns(&["a)|(b"]),
+            ..conf
+        };
+        assert!(find_matches(&conf, contents).is_err());
+        // 空模式匹配所有行, 没有模式时不匹配任何行
+        let conf = Config {
+            patterns: patterns(&["x", ""]),
+            ..Config::default()
+        };
+        assert_eq!(lines(&conf).len(), 4);
+        let conf = Config {
+            patterns: patterns(&[]),
+            ..Config::default()
+        };
+        assert!(lines(&conf).is_empty());
+    }
+
+    #[test]
+    fn test_word_and_line_regexp() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape, duct_tape and duct.";
+        let spans = |conf: &Config| -> Vec<(usize, usize, usize)> {
+            let matches = find_matches(conf, contents).unwrap();
+            matches
+                .iter()
+                .map(|m| (m.line_number, m.start, m.end))
+                .collect()
+        };
+        // productive 和 duct_tape 中的 duct 都不是完整的单词
+        let conf = Config {
+            query: String::from("duct"),
+            word_regexp: true,
+            ..Config::default()
+        };
+        assert_eq!(spans(&conf), vec![(4, 25, 29)]);
+        let conf = Config {
+            ignore_case: true,
+            ..conf
+        };
+        assert_eq!(spans(&conf), vec![(4, 0, 4)]);
+        let conf = Config {
+            patterns: Some(vec![String::from("fast"), String::from("Pic")]),
+            ..conf
+        };
+        assert_eq!(spans(&conf), vec![(2, 6, 10)]);
+        let conf = Config {
+            query: String::from(r"d\w+"),
+            mode: MatchMode::Regex,
+            word_regexp: true,
+            ..Config::default()
+        };
+        assert_eq!(spans(&conf), vec![(4, 11, 20)]);
+        // Unicode 字母也是单词字符
+        let conf = Config {
+            query: String::from("über"),
+            word_regexp: true,
+            ..Config::default()
+        };
+        assert!(find_matches(&conf, "überall\nsüber").unwrap().is_empty());
+
+        let conf = Config {
+            query: String::from("Pick three."),
+            line_regexp: true,
+            word_regexp: true,
+            ..Config::default()
+        };
+        assert_eq!(spans(&conf), vec![(3, 0, 11)]);
+        let conf = Config {
+            query: String::from("RUST:"),
+            ignore_case: true,
+            ..conf
+        };
+        assert_eq!(spans(&conf), vec![(1, 0, 5)]);
+        let conf = Config {
+            query: String::from("Pick"),
+            ..conf
+        };
+        assert!(spans(&conf).is_empty());
+        let conf = Config {
+            patterns: Some(vec![String::from("P"), String::from(r"Pick \w+\.")]),
+            mode: MatchMode::Regex,
+            line_regexp: true,
+            ..Config::default()
+        };
+        assert_eq!(spans(&conf), vec![(3, 0, 11)]);
+    }
+
+    #[test]
+    fn test_fuzzy_sorted_by_score() {
+        let contents = "\
+we recieve it
+we receive it
+nothing here
+we receve it
+";
+        let conf = Config {
+            query: String::from("receive"),
+            mode: MatchMode::Fuzzy(2),
+            line_number: true,
+            sort: Some(SortBy::Score),
+            ..Config::default()
+        };
+        let matches = find_matches(&conf, contents).unwrap();
+        let spans: Vec<_> = matches
+            .iter()
+            .map(|m| (m.line_number, m.matched()))
+            .collect();
+        assert_eq!(spans, vec![(1, "recieve"), (2, "receive"), (4, "receve")]);
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        assert_eq!(
+            search_reader(&conf, contents.as_bytes(), None, &mut printer).unwrap(),
+            3
+        );
+        // 排序的结果在 summary 时才输出, 距离相同的保持原来的顺序
+        assert!(printer.into_inner().is_empty());
+        let mut printer = Printer::new(&conf, &mut out);
+        search_reader(&conf, contents.as_bytes(), None, &mut printer).unwrap();
+        printer.summary().unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2:we receive it\n4:we receve it\n1:we recieve it\n"
+        );
+
+        // -x 时整行和模式的编辑距离不能超过 k
+        let conf = Config {
+            query: String::from("we receive it"),
+            mode: MatchMode::Fuzzy(1),
+            line_regexp: true,
+            ..Config::default()
+        };
+        let matches = find_matches(&conf, contents).unwrap();
+        let lines: Vec<_> = matches.iter().map(|m| m.line_number).collect();
+        assert_eq!(lines, vec![2, 4]);
+    }
+
+    #[test]
+    fn test_fast_search() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
+        for query in ["duct", "Duct", "t", "Rust:", ".", "three.\n", ""] {
+            assert_eq!(
+                fast_search(query, contents).unwrap(),
+                search(false, query, contents).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let conf = Config {
+            query: String::from("fast"),
+            line_number: true,
+            column: true,
+            ..Config::default()
+        };
+        let mut input = Vec::new();
+        for i in 0..5000 {
+            input.extend_from_slice(format!("line {} ", i).as_bytes());
+            input.extend_from_slice(b"\xff\r\n");
+            if i % 1000 == 999 {
+                input.extend_from_slice(b"\xfe so fast\r\n");
+            }
+        }
+        input.extend_from_slice(b"fast at the end");
+        // 很小的缓冲区, 保证有行被切在两块之间
+        let reader = BufReader::with_capacity(100, input.as_slice());
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        let selected = search_reader(&conf, reader, None, &mut printer).unwrap();
+        assert_eq!(selected, 6);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "1001:8:\u{fffd} so fast");
+        assert_eq!(lines[4], "5005:8:\u{fffd} so fast");
+        assert_eq!(lines[5], "5006:1:fast at the end");
+    }
+
+    #[test]
+    fn test_search_reader() {
+        let conf = Config {
+            query: String::from("fast"),
+            line_number: true,
+            ..Config::default()
+        };
+        // 第二行包含不合法的 UTF-8 字节, 第三行以 \r\n 结尾
+        let input: &[u8] = b"Rust:\nsafe, \xff\xfe fast\r\nfast\r\nPick three.";
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        let selected = search_reader(&conf, input, None, &mut printer).unwrap();
+        assert_eq!(selected, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2:safe, \u{fffd}\u{fffd} fast\n3:fast\n"
+        );
+    }
+
+    #[test]
+    fn test_multiline() {
+        let render = |query: &str, mode, extra: fn(&mut Config)| {
+            let mut conf = Config {
+                query: query.to_string(),
+                mode,
+                multiline: true,
+                line_number: true,
+                ..Config::default()
+            };
+            extra(&mut conf);
+            let input: &[u8] = b"fn main(\r\n    a: u8,\n) {\n}\nfn other() {}\n";
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&conf, &mut out);
+            let selected = search_reader(&conf, input, None, &mut printer).unwrap();
+            (selected, String::from_utf8(out).unwrap())
+        };
+        // 匹配覆盖的每一行都输出, \r\n 中的 \r 不属于行的内容
+        assert_eq!(
+            render(r"fn \w+\(\s*a: u8,\n\)", MatchMode::Regex, |_| {}),
+            (3, String::from("1:fn main(\n2:    a: u8,\n3:) {\n"))
+        );
+        assert_eq!(
+            render("{\n}", MatchMode::Literal, |_| {}),
+            (2, String::from("3:) {\n4:}\n"))
+        );
+        // ^ 和 $ 匹配每一行的开头和结尾
+        assert_eq!(
+            render(r"^\}$\n^fn", MatchMode::Regex, |c| c.output =
+                OutputMode::Count),
+            (2, String::from("2\n"))
+        );
+        assert_eq!(
+            render(r"u8,\n\)", MatchMode::Regex, |c| c.output =
+                OutputMode::OnlyMatching),
+            (2, String::from("2:u8,\n3:)\n"))
+        );
+        assert_eq!(
+            render(r"\(\s+a", MatchMode::Regex, |c| c.invert = true),
+            (3, String::from("3:) {\n4:}\n5:fn other() {}\n"))
+        );
+    }
+
+    #[test]
+    fn test_binary_files() {
+        let render = |binary_files, output, input: &[u8]| {
+            let conf = Config {
+                query: String::from("fast"),
+                path: String::from("data.bin"),
+                binary_files,
+                output,
+                ..Config::default()
+            };
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&conf, &mut out);
+            let selected = search_reader(&conf, input, None, &mut printer).unwrap();
+            (selected, String::from_utf8(out).unwrap())
+        };
+        let input: &[u8] = b"fast\0\nslow\nfast again\n";
+        // 找到第一个被选中的行之后就结束了
+        assert_eq!(
+            render(BinaryFiles::Binary, OutputMode::Lines, input),
+            (1, String::from("Binary file data.bin matches\n"))
+        );
+        assert_eq!(
+            render(BinaryFiles::Binary, OutputMode::OnlyMatching, b"slow\0"),
+            (0, String::new())
+        );
+        assert_eq!(
+            render(BinaryFiles::Binary, OutputMode::Count, input),
+            (2, String::from("2\n"))
+        );
+        assert_eq!(
+            render(BinaryFiles::Text, OutputMode::Lines, input),
+            (2, String::from("fast\0\nfast again\n"))
+        );
+        assert_eq!(
+            render(
+                BinaryFiles::WithoutMatch,
+                OutputMode::FilesWithoutMatch,
+                input
+            ),
+            (0, String::from("data.bin\n"))
+        );
+        // 没有 NUL 的输入不受影响
+        assert_eq!(
+            render(BinaryFiles::WithoutMatch, OutputMode::Lines, b"fast\n"),
+            (1, String::from("fast\n"))
+        );
+    }
+
+    #[test]
+    fn test_output_modes() {
+        let render = |output, query: &str, input: &[u8]| {
+            let conf = Config {
+                query: query.to_string(),
+                path: STDIN_PATH.to_string(),
+                output,
+                mode: MatchMode::Regex,
+                ..Config::default()
+            };
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&conf, &mut out);
+            let selected = search_reader(&conf, input, None, &mut printer).unwrap();
+            (selected, String::from_utf8(out).unwrap())
+        };
+        let input = b"one fish two fish\nred fish\nblue";
+        assert_eq!(
+            render(OutputMode::OnlyMatching, r"\w*sh", input),
+            (2, "fish\nfish\nfish\n".to_string())
+        );
+        // 空匹配不输出, 但不能卡在原地
+        assert_eq!(
+            render(OutputMode::OnlyMatching, "x*", input),
+            (3, String::new())
+        );
+        assert_eq!(
+            render(OutputMode::Count, "fish", input),
+            (2, "2\n".to_string())
+        );
+        // -l 找到第一个匹配就停止读取
+        assert_eq!(
+            render(OutputMode::FilesWithMatches, "fish", input),
+            (1, "(standard input)\n".to_string())
+        );
+        assert_eq!(
+            render(OutputMode::FilesWithoutMatch, "fish", input),
+            (1, String::new())
+        );
+        assert_eq!(render(OutputMode::Quiet, "fish", input), (1, String::new()));
+        assert_eq!(render(OutputMode::Quiet, "frog", input), (0, String::new()));
+    }
+
+    #[test]
+    fn test_skip_unreadable_files() {
+        let root = std::env::temp_dir().join(format!("mini_grep_skip_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "fast\n").unwrap();
+        let files = vec![root.join("missing.txt"), root.join("a.txt")];
+        let conf = Config {
+            query: String::from("fast"),
+            ..Config::default()
+        };
+        let matcher = LineMatcher::new(&conf).unwrap();
+        let mut printer = Printer::new(&conf, Vec::new());
+        let errors = search_files(&conf, &matcher, &files, &mut printer).unwrap();
+        assert_eq!(errors, 1);
+        assert_eq!(printer.total(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let outcome = |matched, errors| Outcome {
+            matched,
+            errors,
+            ..Outcome::default()
+        };
+        assert_eq!(outcome(true, 0).exit_code(false), 0);
+        assert_eq!(outcome(false, 0).exit_code(false), 1);
+        assert_eq!(outcome(true, 1).exit_code(false), 2);
+        assert_eq!(outcome(false, 1).exit_code(true), 2);
+        assert_eq!(outcome(true, 1).exit_code(true), 0);
+    }
+
+    #[test]
+    fn test_rewrite_files() {
+        let root = std::env::temp_dir().join(format!("mini_grep_in_place_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a colour\r\nno match\ncolour colour").unwrap();
+        fs::write(root.join("b.txt"), "nothing").unwrap();
+        fs::write(root.join("c.txt"), b"colour \xff").unwrap();
+        let conf = Config {
+            query: String::from("colour"),
+            replace: Some(Replacement::parse("color").unwrap()),
+            in_place: true,
+            backup: Some(String::from(".bak")),
+            ..Config::default()
+        };
+        let matcher = LineMatcher::new(&conf).unwrap();
+        let outcome = rewrite_files(&conf, &matcher, &root).unwrap();
+        // c.txt 不是合法的 UTF-8, 跳过并计为错误
+        assert_eq!((outcome.matched, outcome.errors), (true, 1));
+        let stats = outcome.stats;
+        assert_eq!((stats.files_searched, stats.files_matched), (2, 1));
+        assert_eq!((stats.matched_lines, stats.bytes_scanned), (2, 39));
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt")).unwrap(),
+            "a color\r\nno match\ncolor color"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt.bak")).unwrap(),
+            "a colour\r\nno match\ncolour colour"
+        );
+        // 没有匹配的文件不会被改写, 也没有备份
+        assert!(!root.join("b.txt.bak").exists());
+        assert_eq!(fs::read(root.join("c.txt")).unwrap(), b"colour \xff");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_regex_search() {
         let contents = "\
 Rust:
 safe, fast, productive.
 Pick three.";
-        let results = search(true, query, contents).unwrap();
-        assert_eq!(results, vec!["safe, fast, productive."]);
+        let results = regex_search(false, r"^\w+:$|th(ree|ing)", contents).unwrap();
+        assert_eq!(results, vec!["Rust:", "Pick three."]);
+        let results = regex_search(true, "^rust", contents).unwrap();
+        assert_eq!(results, vec!["Rust:"]);
+        assert!(regex_search(false, "(unclosed", contents).is_err());
     }
 }
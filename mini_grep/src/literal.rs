@@ -0,0 +1,164 @@
+//! 区分大小写的字面匹配, 使用 Boyer-Moore-Horspool 算法
+//!
+//! Horspool 根据窗口最后一个字节查表决定向后跳多远, 模式越长, 平均跳得越远.
+//! 搜索大文件时不需要先把文本切成行: 直接在整块文本上查找, 只在命中的位置附近寻找行的边界.
+
+/// 预处理好跳转表的字面查找器
+///
+/// # Example
+/// ```
+/// use pandastd_mini_grep::literal::Horspool;
+///
+/// let finder = Horspool::new(b"fast");
+/// assert_eq!(finder.find(b"safe, fast, productive."), Some(6));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Horspool {
+    needle: Vec<u8>,
+    // skip[b]: 窗口最后一个字节是 b 时可以向后跳过的字节数, 表比较大, 放在堆上
+    skip: Box<[usize; 256]>,
+}
+
+impl Horspool {
+    pub fn new(needle: &[u8]) -> Horspool {
+        let len = needle.len();
+        let mut skip = Box::new([len; 256]);
+        // 最后一个字节不参与建表, 否则它对应的跳转距离会是 0
+        for (i, &b) in needle.iter().enumerate().take(len.saturating_sub(1)) {
+            skip[b as usize] = len - 1 - i;
+        }
+        Horspool {
+            needle: needle.to_vec(),
+            skip,
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        self.find_at(haystack, 0)
+    }
+
+    /// 从 start 开始查找, 返回匹配开始的位置
+    pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<usize> {
+        let len = self.needle.len();
+        if len == 0 {
+            return (start <= haystack.len()).then_some(start);
+        }
+        // 只有一个字节时跳转表没有意义, 直接逐字节比较, 编译器可以把它向量化
+        if len == 1 {
+            let byte = self.needle[0];
+            return haystack
+                .get(start..)?
+                .iter()
+                .position(|&b| b == byte)
+                .map(|i| start + i);
+        }
+        let last = self.needle[len - 1];
+        let mut pos = start;
+        while pos + len <= haystack.len() {
+            let b = haystack[pos + len - 1];
+            if b == last && haystack[pos..pos + len - 1] == self.needle[..len - 1] {
+                return Some(pos);
+            }
+            pos += self.skip[b as usize];
+        }
+        None
+    }
+
+    /// 在 contents 中找出所有包含 needle 的行, 结果和逐行调用 `str::contains` 完全一样
+    ///
+    /// 行的切分规则和 `str::lines` 相同: 以 `\n` 或 `\r\n` 结尾, 最后一行的换行符可以省略
+    pub fn search_lines<'a>(&self, contents: &'a str) -> Vec<&'a str> {
+        let bytes = contents.as_bytes();
+        // 跨行的查询在逐行搜索时永远不会匹配
+        if self.needle.contains(&b'\n') {
+            return Vec::new();
+        }
+        if self.needle.is_empty() {
+            return contents.lines().collect();
+        }
+        let mut results = Vec::new();
+        let mut pos = 0;
+        while let Some(hit) = self.find_at(bytes, pos) {
+            let (start, end, next) = line_around(bytes, hit);
+            // lines 会去掉 \r\n 中的 \r, 如果匹配用到了这个 \r, 那它在逐行搜索时不算匹配
+            if hit + self.needle.len() <= end {
+                results.push(&contents[start..end]);
+                pos = next;
+            } else {
+                pos = hit + 1;
+            }
+        }
+        results
+    }
+}
+
+/// 返回包含 pos 的那一行的开始位置, 去掉换行符后的结束位置, 以及下一行的开始位置
+pub fn line_around(bytes: &[u8], pos: usize) -> (usize, usize, usize) {
+    let start = bytes[..pos]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    match bytes[pos..].iter().position(|&b| b == b'\n') {
+        Some(i) => {
+            let newline = pos + i;
+            let end = if newline > start && bytes[newline - 1] == b'\r' {
+                newline - 1
+            } else {
+                newline
+            };
+            (start, end, newline + 1)
+        }
+        None => (start, bytes.len(), bytes.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let finder = Horspool::new(b"needle");
+        assert_eq!(finder.find(b"haystack with a needle in it"), Some(16));
+        assert_eq!(finder.find(b"needl"), None);
+        assert_eq!(finder.find_at(b"needle needle", 1), Some(7));
+        assert_eq!(Horspool::new(b"").find_at(b"abc", 3), Some(3));
+        assert_eq!(Horspool::new(b"a").find(b"bbba"), Some(3));
+        assert_eq!(
+            Horspool::new("世界".as_bytes()).find("你好世界".as_bytes()),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn test_search_lines_matches_str_contains() {
+        let texts = [
+            "Rust:\nsafe, fast, productive.\nPick three.",
+            "a\r\nb\r\nab\r\n\r\nba",
+            "fast\nfast fast\n\nfast",
+            "trailing\r",
+            "",
+            "\n\n\n",
+        ];
+        let queries = [
+            "fast", "a", "b", "ab", "\r", "a\r", "three.", "", "x", "st\nf",
+        ];
+        for text in texts {
+            for query in queries {
+                let expected: Vec<&str> = text.lines().filter(|l| l.contains(query)).collect();
+                let finder = Horspool::new(query.as_bytes());
+                assert_eq!(
+                    finder.search_lines(text),
+                    expected,
+                    "query {:?} in {:?}",
+                    query,
+                    text
+                );
+            }
+        }
+    }
+}
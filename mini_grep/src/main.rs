@@ -1,7 +1,8 @@
 // 目标是通过执行 cargo run -- searchstring example-filename.txt
 // 可以在 example-filename.txt 中搜索 searchstring
 
-use std::{env, process};
+use std::path::Path;
+use std::{env, io, process};
 
 fn main() {
     // env 是一个迭代器, 用于迭代所有的环境变量
@@ -27,8 +28,56 @@ fn main() {
         // eprintln! 会将内容输出到标准错误流, 而不是标准输出流
         // 标准错误流会被输出到终端, 而不会被 shell 重定向
         eprintln!("parse arguments failed: {}", err);
-        process::exit(1);
+        // 和 grep 一样, 出错时退出码为 2, 1 留给没有匹配的情况
+        process::exit(2);
     });
+    if conf.debug_config {
+        // 合并了 IGNORE_CASE, MINI_GREP_CONFIG 指向的配置文件和命令行之后实际使用的选项
+        println!("{:#?}", conf);
+        return;
+    }
+    if conf.help {
+        print!("{}", pandastd_mini_grep::USAGE);
+        return;
+    }
+    if conf.type_list {
+        for (name, globs) in pandastd_mini_grep::walk::TYPES {
+            println!("{}: {}", name, globs.join(", "));
+        }
+        return;
+    }
+    if conf.build_index {
+        // index 子命令: 建立或更新索引, 输出统计信息. 有文件读取失败时和搜索一样退出码为 2
+        let stats = pandastd_mini_grep::walk::WalkOptions::from_config(&conf)
+            .and_then(|opts| pandastd_mini_grep::index::update(Path::new(&conf.path), &opts));
+        match stats {
+            Ok(stats) => {
+                println!("{}", stats);
+                process::exit(if stats.errors > 0 { 2 } else { 0 });
+            }
+            Err(err) => {
+                eprintln!("mini_grep: {}", err);
+                process::exit(2);
+            }
+        }
+    }
+    if conf.interactive {
+        // 选中的 `path:line` 输出到标准输出, 没有选择任何行时退出码为 1
+        match pandastd_mini_grep::interactive::run(&conf) {
+            Ok(Some(selection)) => println!("{}", selection),
+            Ok(None) => process::exit(1),
+            Err(err) => {
+                eprintln!("mini_grep: {}", err);
+                process::exit(2);
+            }
+        }
+        return;
+    }
+    if conf.version {
+        // CARGO_PKG_VERSION 是 cargo 在编译时注入的环境变量, 值就是 Cargo.toml 中的 version
+        println!("mini_grep {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
 
     // 读取文件
     // let contents = match fs::read_to_string(conf.path) {
@@ -41,8 +90,28 @@ fn main() {
     // eprintln!("With text:\n{}", contents)
     // [ 优化后 ]
     // 这种写法就很像 go 的错误处理机制, if err != nil { handle err }
-    if let Err(err) = pandastd_mini_grep::run(conf) {
-        eprintln!("read file failed: {}", err);
-        process::exit(1);
+    let quiet = conf.output == pandastd_mini_grep::OutputMode::Quiet;
+    let stats = conf.stats;
+    let json = conf.output == pandastd_mini_grep::OutputMode::Json;
+    match pandastd_mini_grep::run(conf) {
+        // 退出码: 有被选中的行为 0, 没有为 1, 有文件读取失败为 2, 脚本可以配合 -q 使用
+        Ok(outcome) => {
+            // 统计信息输出到标准错误, 不混进 -l / -c 之类要交给脚本处理的结果.
+            // --json 时作为 stats 事件跟在其他事件后面, 标准输出仍然每行一个 JSON 对象
+            if stats && json {
+                let written = pandastd_mini_grep::json::stats(&mut io::stdout(), &outcome.stats);
+                if let Err(err) = written {
+                    eprintln!("mini_grep: {}", err);
+                    process::exit(2);
+                }
+            } else if stats {
+                eprintln!("{}", outcome.stats);
+            }
+            process::exit(outcome.exit_code(quiet))
+        }
+        Err(err) => {
+            eprintln!("mini_grep: {}", err);
+            process::exit(2);
+        }
     }
 }
@@ -0,0 +1,174 @@
+//! 行内匹配的统一接口
+//!
+//! 各种查找器的接口不完全一样: [`Horspool`] 在字节上查找, 只返回开始位置; [`Fuzzy`] 还会返回编辑距离.
+//! [`Matcher`] 把它们统一成 "在一行中从某个位置开始, 找到第一个匹配的字节区间",
+//! [`crate::searcher::Searcher`] 只依赖这个 trait, 所以也可以接入库的使用者自己实现的匹配器.
+//!
+//! 按照 [`crate::Config`] 组合好的匹配器 (包括多个模式, -w 和 -x) 由 [`crate::build_matcher`] 创建.
+
+use crate::aho_corasick::AhoCorasick;
+use crate::fold::CaseInsensitive;
+use crate::fuzzy::Fuzzy;
+use crate::literal::Horspool;
+use crate::regex::Regex;
+
+/// 在一行文本中查找匹配
+///
+/// # Example
+/// ```
+/// use pandastd_mini_grep::literal::Horspool;
+/// use pandastd_mini_grep::matcher::Matcher;
+///
+/// let finder = Horspool::new(b"ab");
+/// let mut found = Vec::new();
+/// finder.find_into("ab cab", true, &mut found);
+/// assert_eq!(found, vec![(0, 2), (4, 6)]);
+/// ```
+pub trait Matcher {
+    /// 从 start 开始查找, 返回第一个匹配在整行中的字节区间. start 总是在字符边界上
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)>;
+
+    /// 返回第一个匹配的字节区间
+    fn find(&self, line: &str) -> Option<(usize, usize)> {
+        self.find_at(line, 0)
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.find(line).is_some()
+    }
+
+    /// 把行内的匹配区间放进 found, all 为 false 时只找第一个. found 由调用者复用, 不用每行都分配
+    fn find_into(&self, line: &str, all: bool, found: &mut Vec<(usize, usize)>) {
+        found.clear();
+        let mut start = 0;
+        while let Some((s, e)) = self.find_at(line, start) {
+            found.push((s, e));
+            if !all {
+                break;
+            }
+            start = if s < e {
+                e
+            } else {
+                // 空匹配时向后移动一个字符, 否则会一直停在原地
+                match line[e..].chars().next() {
+                    Some(c) => e + c.len_utf8(),
+                    None => break,
+                }
+            };
+        }
+    }
+
+    /// --sort=score 的分数, 越小越好. found 是 find_into 在这一行中找到的匹配.
+    /// 默认总是 0, 模糊匹配是第一个匹配和模式的编辑距离
+    fn score(&self, _line: &str, _found: &[(usize, usize)]) -> usize {
+        0
+    }
+
+    /// 匹配器只是在查找一个字节串时返回对应的 [`Horspool`], 默认返回 None.
+    /// 这时 [`crate::searcher::Searcher`] 可以在整块输入上查找, 不用逐行解码
+    fn as_literal(&self) -> Option<&Horspool> {
+        None
+    }
+}
+
+impl Matcher for Horspool {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        let s = Horspool::find_at(self, line.as_bytes(), start)?;
+        Some((s, s + self.needle().len()))
+    }
+
+    fn as_literal(&self) -> Option<&Horspool> {
+        Some(self)
+    }
+}
+
+// 字面匹配和前面的文本无关, 直接在 start 之后的部分查找
+impl Matcher for CaseInsensitive {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        let (s, e) = CaseInsensitive::find(self, &line[start..])?;
+        Some((start + s, start + e))
+    }
+}
+
+impl Matcher for AhoCorasick {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        let (s, e) = AhoCorasick::find(self, &line[start..])?;
+        Some((start + s, start + e))
+    }
+}
+
+// 正则的 ^ 和 \b 需要知道前面的字符, 所以不能只看 start 之后的部分
+impl Matcher for Regex {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        Regex::find_at(self, line, start)
+    }
+}
+
+// 只返回区间, 编辑距离通过 score 取得
+impl Matcher for Fuzzy {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        let (s, e, _) = Fuzzy::find(self, &line[start..])?;
+        Some((start + s, start + e))
+    }
+
+    fn score(&self, line: &str, found: &[(usize, usize)]) -> usize {
+        found
+            .first()
+            .map_or(0, |&(s, e)| self.distance(&line[s..e]))
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        (**self).find_at(line, start)
+    }
+
+    fn score(&self, line: &str, found: &[(usize, usize)]) -> usize {
+        (**self).score(line, found)
+    }
+
+    fn as_literal(&self) -> Option<&Horspool> {
+        (**self).as_literal()
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        (**self).find_at(line, start)
+    }
+
+    fn score(&self, line: &str, found: &[(usize, usize)]) -> usize {
+        (**self).score(line, found)
+    }
+
+    fn as_literal(&self) -> Option<&Horspool> {
+        (**self).as_literal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_at_offsets() {
+        let line = "Fast, fast, FAST";
+        let matchers: Vec<Box<dyn Matcher>> = vec![
+            Box::new(Horspool::new(b"fast")),
+            Box::new(CaseInsensitive::new("fast")),
+            Box::new(AhoCorasick::new(&["fast"], true)),
+            Box::new(Regex::new("fast").unwrap()),
+            Box::new(Fuzzy::new("fast", 0, false)),
+        ];
+        for matcher in &matchers {
+            // 所有实现返回的都是整行中的位置, 而不是相对于 start 的位置
+            assert_eq!(matcher.find_at(line, 1), Some((6, 10)));
+        }
+        let mut found = Vec::new();
+        matchers[1].find_into(line, true, &mut found);
+        assert_eq!(found, vec![(0, 4), (6, 10), (12, 16)]);
+        // 空匹配不会停在原地
+        Horspool::new(b"").find_into("ab", true, &mut found);
+        assert_eq!(found, vec![(0, 0), (1, 1), (2, 2)]);
+    }
+}
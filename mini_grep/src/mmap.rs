@@ -0,0 +1,119 @@
+//! 把文件映射到内存中只读访问, 需要开启 `mmap` feature
+//!
+//! 普通的搜索通过 [`std::io::BufReader`] 每次把 64 KiB 复制到读缓冲区. 映射之后文件内容直接出现在进程的地址空间里,
+//! `&[u8]` 本身就实现了 [`std::io::BufRead`], 所以可以原样交给搜索, 由内核按需把页面读进来.
+//! 没有引入 libc, 只声明了需要的两个系统调用, 所以只支持 64 位的 unix (off_t 是 64 位).
+//!
+//! 映射期间文件被其他进程截断时, 访问超出新长度的部分会收到 SIGBUS, 这是 mmap 本身的限制.
+//! 所以只用于足够大 (见 [`MIN_LEN`]) 的普通文件, 小文件直接读取反而更快.
+
+use std::ffi::{c_int, c_void};
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+
+/// 小于这个大小的文件不映射, 建立映射的开销比复制几块读缓冲区还大
+pub(crate) const MIN_LEN: u64 = 4 * 1024 * 1024;
+
+const PROT_READ: c_int = 1;
+const MAP_PRIVATE: c_int = 2;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+/// 一个只读的文件映射, drop 时解除映射
+pub(crate) struct Mmap {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+// 映射是只读的, 可以在线程之间共享
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// 映射整个文件. 不是普通文件或者小于 MIN_LEN 时返回 None, 调用者照常读取
+    pub(crate) fn open(file: &File) -> io::Result<Option<Mmap>> {
+        let meta = file.metadata()?;
+        if !meta.is_file() || meta.len() < MIN_LEN {
+            return Ok(None);
+        }
+        Mmap::map(file, meta.len())
+    }
+
+    fn map(file: &File, len: u64) -> io::Result<Option<Mmap>> {
+        let Ok(len) = usize::try_from(len) else {
+            return Ok(None);
+        };
+        // 长度为 0 的映射会失败, 空文件也没有映射的必要
+        if len == 0 {
+            return Ok(None);
+        }
+        // SAFETY: 让内核选择地址, 映射 fd 的 [0, len), 失败时返回 MAP_FAILED 而不会访问内存
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ,
+                MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        // MAP_FAILED 是 (void *) -1
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(Mmap { ptr, len }))
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: ptr 指向 len 个可读的字节, 在 drop 之前一直有效
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: ptr 和 len 是 mmap 返回的映射, 只会解除一次
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn test_map_file() {
+        let path = env::temp_dir().join(format!("mini_grep_mmap_{}", std::process::id()));
+        let contents = "hello\nmapped world\n".repeat(1000);
+        fs::write(&path, &contents).unwrap();
+        let file = File::open(&path).unwrap();
+        // 小文件不映射
+        assert!(Mmap::open(&file).unwrap().is_none());
+        let map = Mmap::map(&file, contents.len() as u64).unwrap().unwrap();
+        assert_eq!(&map[..], contents.as_bytes());
+        drop(map);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+//! 多线程搜索多个文件
+//!
+//! 主线程通过 mpsc 通道把文件分发给固定数量的工作线程, 工作线程把每个文件的输出写进自己的缓冲区,
+//! 再通过另一个通道发回主线程. 主线程按照文件原本的顺序输出这些缓冲区,
+//! 所以每个文件的输出都是连续的, 整体顺序也和单线程搜索完全一样.
+//! 主线程只在前面的文件输出之后才继续分发任务, 所以一个很慢的文件不会让后面所有文件的输出都堆在内存里.
+//! -q 时找到被选中的行后不再分发新的任务.
+//! 无法读取的文件也按照原本的顺序报告并跳过.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::printer::Printer;
+use crate::{report, search_file, Config, Error, LineMatcher, OutputMode};
+
+/// 用 jobs 个线程搜索 files, 结果按 files 的顺序交给 printer 输出, 返回跳过的文件数量.
+/// by_matches 为 true 时 (--sort=matches) 等所有文件都搜索完, 再按被选中的行数从多到少输出
+pub(crate) fn search_files<W: Write>(
+    conf: &Config,
+    matcher: &LineMatcher,
+    files: &[PathBuf],
+    jobs: usize,
+    by_matches: bool,
+    printer: &mut Printer<W>,
+) -> Result<usize, Error> {
+    let quiet = conf.output == OutputMode::Quiet;
+    // 发出的任务最多比还没输出的第一个文件领先这么多个, 乱序到达后等待输出的结果也不会超过这个数量
+    let window = jobs.max(1) * 4;
+    let (job_tx, job_rx) = mpsc::channel::<(usize, &PathBuf)>();
+    // Receiver 不能被多个线程同时使用, 所以用 Mutex 包起来, 每次只有一个线程能取任务
+    let job_rx = Mutex::new(job_rx);
+    // -q 时已经找到被选中的行, 工作线程不用再搜索已经取到的任务
+    let stop = AtomicBool::new(false);
+    let (result_tx, result_rx) = mpsc::channel();
+
+    // scope 保证所有线程在函数返回前结束, 所以线程里可以直接借用 conf, matcher 和 files
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(files.len()) {
+            let result_tx = result_tx.clone();
+            let (job_rx, stop) = (&job_rx, &stop);
+            scope.spawn(move || loop {
+                // 取到任务后立刻释放锁, 其他线程才能继续取任务
+                let job = job_rx.lock().unwrap().recv();
+                let Ok((index, path)) = job else {
+                    break;
+                };
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let output = search_to_buffer(conf, matcher, path);
+                // 主线程已经因为错误提前返回时发送会失败, 此时也没必要继续搜索了
+                if result_tx.send((index, output)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        // 按顺序发送任务, 直到发出 limit 个. 全部发完后 drop 发送端, 工作线程取完任务就会退出
+        let mut job_tx = Some(job_tx);
+        let mut pending = files.iter().enumerate();
+        let mut dispatched = 0;
+        let mut send = |job_tx: &mut Option<mpsc::Sender<_>>, limit: usize| {
+            while let Some(tx) = job_tx {
+                if dispatched >= limit {
+                    break;
+                }
+                match pending.next() {
+                    Some(job) => {
+                        tx.send(job).unwrap();
+                        dispatched += 1;
+                    }
+                    None => *job_tx = None,
+                }
+            }
+        };
+        send(&mut job_tx, window);
+
+        // 结果到达的顺序是不确定的, 先放进 BTreeMap, 轮到它时再输出
+        let mut waiting = BTreeMap::new();
+        let mut next = 0;
+        let mut errors = 0;
+        // -q 时第一个有被选中的行的文件, 之后的文件不再输出, 和单线程搜索一样
+        let mut last = files.len();
+        let mut emit = |output: Buffered| {
+            match output {
+                Ok((output, selected, bytes)) => printer.append(&output, selected, bytes)?,
+                Err(err) => {
+                    report(&err);
+                    errors += 1;
+                }
+            }
+            Ok::<_, Error>(())
+        };
+        for (received, (index, output)) in result_rx.into_iter().enumerate() {
+            if quiet && matches!(output, Ok((_, selected, _)) if selected > 0) {
+                last = last.min(index);
+                stop.store(true, Ordering::Relaxed);
+                job_tx = None;
+            }
+            waiting.insert(index, output);
+            if !by_matches {
+                while let Some(output) = waiting.remove(&next) {
+                    if next <= last {
+                        emit(output)?;
+                    }
+                    next += 1;
+                }
+            }
+            // --sort=matches 时所有结果都要留到最后, 只限制正在搜索的任务数量
+            send(
+                &mut job_tx,
+                if by_matches { received + 1 } else { next } + window,
+            );
+        }
+        // 剩下的是 --sort=matches 的全部结果, 或者 -q 提前停止时没有轮到的结果.
+        // 稳定排序, 行数相同时保持原来的顺序. 读取失败的文件当作没有被选中的行
+        waiting.retain(|&index, _| index <= last);
+        let mut rest: Vec<Buffered> = waiting.into_values().collect();
+        if by_matches {
+            rest.sort_by_key(|output| {
+                Reverse(output.as_ref().map_or(0, |&(_, selected, _)| selected))
+            });
+        }
+        for output in rest {
+            emit(output)?;
+        }
+        Ok(errors)
+    })
+}
+
+// 一个文件的输出, 被选中的行数和读取的字节数
+type Buffered = Result<(Vec<u8>, usize, u64), Error>;
+
+// 输出到 Vec 不会失败, 所以这里只会有读取文件的错误
+fn search_to_buffer(conf: &Config, matcher: &LineMatcher, path: &Path) -> Buffered {
+    let mut printer = Printer::new(conf, Vec::new());
+    let selected = search_file(conf, matcher, path, &mut printer)?;
+    let bytes = printer.stats().bytes_scanned;
+    Ok((printer.into_inner(), selected, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn test_output_order_matches_sequential() {
+        let root = env::temp_dir().join(format!("mini_grep_parallel_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut files = Vec::new();
+        for i in 0..40 {
+            let path = root.join(format!("{:02}.txt", i));
+            // 文件大小不一样, 让线程完成的顺序被打乱
+            let contents = "skip\nhit line\n".repeat((40 - i) * 50);
+            fs::write(&path, contents).unwrap();
+            files.push(path);
+        }
+        let conf = Config {
+            query: String::from("hit"),
+            output: OutputMode::Count,
+            ..Config::default()
+        };
+        let matcher = LineMatcher::new(&conf).unwrap();
+        let render = |jobs: usize| {
+            let mut printer = Printer::new(&conf, Vec::new());
+            if jobs == 1 {
+                for file in &files {
+                    search_file(&conf, &matcher, file, &mut printer).unwrap();
+                }
+            } else {
+                search_files(&conf, &matcher, &files, jobs, false, &mut printer).unwrap();
+            }
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+        let sequential = render(1);
+        assert_eq!(sequential.lines().count(), 40);
+        assert_eq!(render(4), sequential);
+        assert_eq!(render(64), sequential);
+
+        // 无法读取的文件被跳过, 其他文件的输出不受影响
+        files.insert(3, root.join("missing.txt"));
+        let mut printer = Printer::new(&conf, Vec::new());
+        let errors = search_files(&conf, &matcher, &files, 4, false, &mut printer).unwrap();
+        assert_eq!(errors, 1);
+        let stats = printer.stats();
+        assert_eq!((stats.files_searched, stats.matched_lines), (40, 41000));
+        assert_eq!(stats.bytes_scanned, 14 * 41000);
+        assert_eq!(String::from_utf8(printer.into_inner()).unwrap(), sequential);
+
+        // --sort=matches: 文件越靠后行数越少, 倒过来之后按行数排序又得到原来的顺序
+        files.reverse();
+        for jobs in [1, 4] {
+            let mut printer = Printer::new(&conf, Vec::new());
+            search_files(&conf, &matcher, &files, jobs, true, &mut printer).unwrap();
+            assert_eq!(String::from_utf8(printer.into_inner()).unwrap(), sequential);
+        }
+
+        // -q: 第一个文件就有被选中的行, 之后不再分发任务, 最多只搜索了一个窗口的文件
+        let quiet = Config {
+            output: OutputMode::Quiet,
+            ..conf.clone()
+        };
+        let mut printer = Printer::new(&quiet, Vec::new());
+        search_files(&quiet, &matcher, &files, 4, false, &mut printer).unwrap();
+        assert!(printer.total() > 0);
+        assert!(printer.stats().files_searched <= 16);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,802 @@
+//! 搜索结果的输出格式, 和 GNU grep 保持一致
+//!
+//! - 匹配行: `path:行号:列号:内容`
+//! - 上下文行: `path-行号-内容`
+//! - 两组不相邻的结果之间输出一行 `--`
+//!
+//! path, 行号和列号都是可选的, 分别由是否搜索目录, `-n` 和 `--column` 决定.
+//! `path:行号:列号` 这种格式可以直接被编辑器识别并跳转.
+//!
+//! 其他输出方式见 [`OutputMode`]: -o 时每个匹配单独一行, 格式同上, 列号是这个匹配的列号;
+//! -c 输出 `path:行数`; -l / -L 只输出 path. 从标准输入读取时 path 是 `(standard input)`.
+//!
+//! 输出颜色时, 被选中的行内所有匹配的部分都会高亮, 颜色见 [`crate::color`].
+//! `--replace` 时被选中的行中所有匹配都被替换后再输出, -o 时输出每个匹配替换后的文本, 高亮的是替换后的部分.
+//! `--json` 的格式见 [`crate::json`].
+//!
+//! 二进制输入 (见 [`Printer::set_binary`]) 不输出匹配的行, 第一个被选中的行换成一行 `Binary file X matches`.
+//!
+//! `--sort=score` 时被选中的行 (-o 时是每个匹配) 不会立即输出, 而是和分数一起缓存起来,
+//! 到 [`Printer::summary`] 时按分数排序后输出. 其他输出方式不受影响.
+
+use std::io::{Error, Write};
+use std::path::Path;
+
+use crate::color::{self, Colors};
+use crate::config::ColorChoice;
+use crate::replace::Replacement;
+use crate::searcher::{Context, ContextKind, Sink, SinkLine};
+use crate::{json, Config, OutputMode, SearchStats, SortBy, STDIN_PATH};
+
+/// 把一个或多个文件的搜索结果写到 out 中
+///
+/// 通常作为 [`Sink`] 交给 [`crate::searcher::Searcher`], 由它决定哪些行被选中, 哪些是上下文.
+/// 也可以逐行输入: 每个文件先调用 begin, 再对每一行调用 line, 最后调用 finish, 这时上下文的处理和 Searcher 完全一样.
+/// 只有 -B 需要的前置上下文会被暂存, 所以内存占用和文件大小无关.
+pub struct Printer<W: Write> {
+    out: W,
+    mode: OutputMode,
+    line_number: bool,
+    column: bool,
+    invert: bool,
+    before: usize,
+    after: usize,
+    // 为 None 时不输出颜色
+    colors: Option<Colors>,
+    replace: Option<Replacement>,
+    // --sort=score 时缓存的 (分数, 输出的内容), 为 None 时直接输出
+    ranked: Option<Vec<(usize, Vec<u8>)>>,
+    // 下一个输出的行的分数, 由 Sink::matched 设置
+    score: usize,
+    // write_line 复用的缓冲区
+    buffer: Vec<u8>,
+    // 是否已经输出过结果, 用来决定新的一组结果前面要不要加 `--`
+    printed: bool,
+    // begin 没有传 path 时使用的文件名, 见 name
+    default_name: String,
+    // 是否在每一行前面输出 begin 传入的 path, 见 with_filename
+    with_filename: bool,
+    // 所有文件被选中的行数之和
+    total: usize,
+    // 已经结束的文件数量, 以及其中有被选中的行的文件数量
+    files: usize,
+    files_with_matches: usize,
+    // 所有文件读取的字节数, 由 Searcher 通过 Sink::scanned 告诉 printer
+    bytes: u64,
+    // 以下是当前文件的状态, 每次 begin 时重置
+    path: Option<String>,
+    // 逐行输入时的上下文状态, 见 line
+    context: Context,
+    // --json 时是否已经输出了这个文件的 begin 事件
+    began: bool,
+    // 当前文件是否是二进制文件, 见 set_binary
+    binary: bool,
+    last_printed: Option<usize>,
+    selected: usize,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(conf: &Config, out: W) -> Printer<W> {
+        Printer {
+            out,
+            mode: conf.output,
+            line_number: conf.line_number,
+            column: conf.column,
+            invert: conf.invert,
+            before: conf.before_context,
+            after: conf.after_context,
+            colors: (conf.color == ColorChoice::Always).then(Colors::from_env),
+            replace: conf.replace.clone(),
+            ranked: (conf.sort == Some(SortBy::Score)).then(Vec::new),
+            score: 0,
+            buffer: Vec::new(),
+            printed: false,
+            default_name: match conf.path.as_str() {
+                STDIN_PATH => String::from("(standard input)"),
+                path => path.to_string(),
+            },
+            with_filename: true,
+            total: 0,
+            files: 0,
+            files_with_matches: 0,
+            bytes: 0,
+            path: None,
+            context: Context::new(conf.invert, conf.before_context, conf.after_context),
+            began: false,
+            binary: false,
+            last_printed: None,
+            selected: 0,
+        }
+    }
+
+    /// 是否在每一行前面输出文件路径, 默认是. 为 false 时即使 begin 传了 path 也不输出,
+    /// 例如只搜索一个文件时, path 只用于 -l 和错误信息
+    pub(crate) fn with_filename(mut self, yes: bool) -> Printer<W> {
+        self.with_filename = yes;
+        self
+    }
+
+    /// 开始输出一个文件, path 为 None 时输出的行不带文件路径前缀
+    pub fn begin(&mut self, path: Option<&Path>) {
+        self.path = path.map(|p| p.display().to_string());
+        self.context.reset();
+        self.began = false;
+        self.binary = false;
+        self.last_printed = None;
+        self.selected = 0;
+    }
+
+    /// 输入一行, number 是从 1 开始的行号, offset 是行首在文件中的字节偏移,
+    /// found 是行内匹配的字节区间, 没有匹配时为空
+    ///
+    /// 只有 -o, --json 和输出颜色时需要行内所有的匹配 (见 needs_all_matches), 其他时候只需要传第一个匹配.
+    /// -v 时输出没有匹配的行, 被选中的行前后的行作为上下文输出.
+    /// 这里没有匹配器, --sort=score 时分数都是 0, 需要分数时通过 [`crate::searcher::Searcher`] 搜索
+    pub fn line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        found: &[(usize, usize)],
+    ) -> Result<(), crate::Error> {
+        // context 要把 self 当作 sink, 所以先取出来
+        let mut context = std::mem::take(&mut self.context);
+        let result = context.line(self, number, offset, line, found, || 0);
+        self.context = context;
+        result.map(drop)
+    }
+
+    // 一个被选中的行
+    fn select(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        found: &[(usize, usize)],
+    ) -> Result<(), Error> {
+        self.selected += 1;
+        if self.hides_lines() {
+            return self.binary_matches();
+        }
+        match self.mode {
+            OutputMode::Lines | OutputMode::Json => {
+                self.separate(number)?;
+                self.emit(number, offset, line, found, true)?;
+                self.printed = true;
+                self.last_printed = Some(number);
+                Ok(())
+            }
+            // -v 时被选中的行没有匹配的部分, 什么都不输出
+            OutputMode::OnlyMatching if !self.invert => {
+                for &(start, end) in found {
+                    // 空匹配没有可以输出的内容
+                    if start == end {
+                        continue;
+                    }
+                    let mut part = String::new();
+                    match &self.replace {
+                        Some(replace) => replace.expand(&line[start..end], &mut part),
+                        None => part.push_str(&line[start..end]),
+                    }
+                    self.write_line(number, Some(start + 1), &part, &[(0, part.len())], ":")?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // 一个上下文行, 只有默认的输出方式和 --json 会输出
+    fn context_line(&mut self, number: usize, offset: usize, line: &str) -> Result<(), Error> {
+        let with_context = [OutputMode::Lines, OutputMode::Json].contains(&self.mode);
+        if !with_context || self.hides_lines() {
+            return Ok(());
+        }
+        self.separate(number)?;
+        self.emit(number, offset, line, &[], false)?;
+        self.last_printed = Some(number);
+        Ok(())
+    }
+
+    // 有上下文时, 和上一个输出的行不相邻的行前面要先输出 `--`. 不同文件的行总是不相邻的
+    fn separate(&mut self, number: usize) -> Result<(), Error> {
+        let contiguous = self.last_printed.is_some_and(|last| last + 1 == number);
+        let context = self.before > 0 || self.after > 0;
+        if self.printed && !contiguous && context && self.mode == OutputMode::Lines {
+            self.write_group_separator()?;
+        }
+        Ok(())
+    }
+
+    // 输出一个被选中的行或者上下文行
+    fn emit(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        found: &[(usize, usize)],
+        selected: bool,
+    ) -> Result<(), Error> {
+        if self.mode == OutputMode::Json {
+            let name = self.path.as_ref().unwrap_or(&self.default_name);
+            if !self.began {
+                json::begin(&mut self.out, name)?;
+                self.began = true;
+            }
+            let kind = if selected { "match" } else { "context" };
+            return json::line(&mut self.out, kind, name, number, offset, line, found);
+        }
+        let column = found.first().map(|&(start, _)| start + 1);
+        let sep = if selected { ":" } else { "-" };
+        match &self.replace {
+            // 列号仍然是替换之前的位置
+            Some(replace) if !found.is_empty() => {
+                let (line, spans) = replace.apply(line, found);
+                self.write_line(number, column, &line, &spans, sep)
+            }
+            _ => self.write_line(number, column, line, found, sep),
+        }
+    }
+
+    /// 把当前文件标记为二进制文件, 在 begin 之后调用. 默认输出和 -o 时不输出任何行,
+    /// 第一个被选中的行换成 `Binary file X matches`, 之后这个文件就结束了 (见 is_done)
+    pub fn set_binary(&mut self, binary: bool) {
+        self.binary = binary;
+    }
+
+    // 二进制文件的行不能直接输出到终端, 只有 -c / -l / -L / -q 和 --json 照常输出
+    fn hides_lines(&self) -> bool {
+        self.binary && [OutputMode::Lines, OutputMode::OnlyMatching].contains(&self.mode)
+    }
+
+    fn binary_matches(&mut self) -> Result<(), Error> {
+        let name = self.path.as_ref().unwrap_or(&self.default_name);
+        let message = format!("Binary file {} matches\n", name);
+        match &mut self.ranked {
+            Some(ranked) => ranked.push((self.score, message.into_bytes())),
+            None => self.out.write_all(message.as_bytes())?,
+        }
+        self.printed = true;
+        Ok(())
+    }
+
+    // 是否按分数排序输出, 是的话 Searcher 要为每个被选中的行计算分数
+    pub(crate) fn ranks(&self) -> bool {
+        self.ranked.is_some()
+    }
+
+    /// 是否需要行内所有的匹配, 而不只是第一个
+    pub fn needs_all_matches(&self) -> bool {
+        let highlight = self.colors.is_some() || self.replace.is_some();
+        [OutputMode::OnlyMatching, OutputMode::Json].contains(&self.mode)
+            || (highlight && self.mode == OutputMode::Lines)
+    }
+
+    /// 当前文件的结果是否已经确定, 确定之后剩下的行不用再读了
+    ///
+    /// -l / -L / -q 只关心有没有被选中的行, 找到第一个就够了; 二进制文件输出 `Binary file X matches` 之后也结束了
+    pub fn is_done(&self) -> bool {
+        let modes = [
+            OutputMode::FilesWithMatches,
+            OutputMode::FilesWithoutMatch,
+            OutputMode::Quiet,
+        ];
+        self.selected > 0 && (modes.contains(&self.mode) || self.hides_lines())
+    }
+
+    /// 结束当前文件, -c / -l / -L 和 --json 的 end 事件在这里输出, 返回被选中的行数
+    pub fn finish(&mut self) -> Result<usize, Error> {
+        self.total += self.selected;
+        self.files += 1;
+        if self.selected > 0 {
+            self.files_with_matches += 1;
+        }
+        let colors = self.colors.as_ref();
+        let name = self.path.as_ref().unwrap_or(&self.default_name);
+        let print_name = match self.mode {
+            OutputMode::Count => {
+                if let Some(path) = self.path.as_ref().filter(|_| self.with_filename) {
+                    color::paint(&mut self.out, colors.map(|c| c.path.as_str()), path)?;
+                    color::paint(&mut self.out, colors.map(|c| c.separator.as_str()), ':')?;
+                }
+                writeln!(self.out, "{}", self.selected)?;
+                false
+            }
+            OutputMode::FilesWithMatches => self.selected > 0,
+            OutputMode::FilesWithoutMatch => self.selected == 0,
+            OutputMode::Json if self.began => {
+                json::end(&mut self.out, name, self.selected)?;
+                false
+            }
+            _ => false,
+        };
+        if print_name {
+            color::paint(&mut self.out, colors.map(|c| c.path.as_str()), name)?;
+            writeln!(self.out)?;
+        }
+        Ok(self.selected)
+    }
+
+    /// 当前文件的名字, 用于 -l / -L 的输出和错误信息
+    ///
+    /// begin 没有传 path 时 (搜索单个文件或标准输入) 是 Config.path, 标准输入是 `(standard input)`
+    pub fn name(&self) -> &str {
+        self.path.as_deref().unwrap_or(&self.default_name)
+    }
+
+    // 每一行前面输出的路径
+    fn label(&self) -> Option<&str> {
+        self.path.as_deref().filter(|_| self.with_filename)
+    }
+
+    /// 所有已经结束的文件中被选中的行数之和
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// 到目前为止的统计信息. Printer 不知道搜索什么时候开始, 所以 elapsed 总是 0
+    pub fn stats(&self) -> SearchStats {
+        SearchStats {
+            files_searched: self.files,
+            files_matched: self.files_with_matches,
+            matched_lines: self.total,
+            bytes_scanned: self.bytes,
+            elapsed: Default::default(),
+        }
+    }
+
+    /// 所有文件都结束之后调用, --json 时输出 summary 事件, --sort=score 时输出排序后的结果
+    pub fn summary(&mut self) -> Result<(), Error> {
+        if let Some(mut ranked) = self.ranked.take() {
+            // 稳定排序, 分数相同时保持搜索到的顺序
+            ranked.sort_by_key(|&(score, _)| score);
+            for (_, output) in ranked {
+                self.out.write_all(&output)?;
+            }
+            self.ranked = Some(Vec::new());
+        }
+        if self.mode == OutputMode::Json {
+            json::summary(
+                &mut self.out,
+                self.files,
+                self.files_with_matches,
+                self.total,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 直接输出另一个 Printer 缓冲好的一个文件的全部结果, 多线程搜索时使用.
+    /// selected 和 bytes 是这个文件被选中的行数和读取的字节数
+    pub(crate) fn append(
+        &mut self,
+        output: &[u8],
+        selected: usize,
+        bytes: u64,
+    ) -> Result<(), Error> {
+        self.total += selected;
+        self.bytes += bytes;
+        self.files += 1;
+        if selected > 0 {
+            self.files_with_matches += 1;
+        }
+        if output.is_empty() {
+            return Ok(());
+        }
+        let context = self.before > 0 || self.after > 0;
+        if self.printed && self.mode == OutputMode::Lines && context {
+            self.write_group_separator()?;
+        }
+        self.out.write_all(output)?;
+        self.printed = true;
+        Ok(())
+    }
+
+    /// 取回 out, 例如输出到 Vec<u8> 时取回缓冲区
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // 两组不相邻的结果之间的 `--`
+    fn write_group_separator(&mut self) -> Result<(), Error> {
+        let color = self.colors.as_ref().map(|c| c.separator.as_str());
+        color::paint(&mut self.out, color, "--")?;
+        writeln!(self.out)
+    }
+
+    // sep 为 ":" 时是被选中的行, 为 "-" 时是上下文行. found 中的区间会被高亮
+    fn write_line(
+        &mut self,
+        number: usize,
+        column: Option<usize>,
+        line: &str,
+        found: &[(usize, usize)],
+        sep: &str,
+    ) -> Result<(), Error> {
+        // 先写到缓冲区里, 排序时直接存起来, 否则整行一次写到 out
+        let mut output = std::mem::take(&mut self.buffer);
+        output.clear();
+        self.render_line(&mut output, number, column, line, found, sep)?;
+        match &mut self.ranked {
+            Some(ranked) => ranked.push((self.score, output)),
+            None => {
+                self.out.write_all(&output)?;
+                self.buffer = output;
+            }
+        }
+        Ok(())
+    }
+
+    fn render_line(
+        &self,
+        out: &mut Vec<u8>,
+        number: usize,
+        column: Option<usize>,
+        line: &str,
+        found: &[(usize, usize)],
+        sep: &str,
+    ) -> Result<(), Error> {
+        let colors = self.colors.as_ref();
+        let separator = colors.map(|c| c.separator.as_str());
+        let line_number = colors.map(|c| c.line_number.as_str());
+        if let Some(path) = self.label() {
+            color::paint(out, colors.map(|c| c.path.as_str()), path)?;
+            color::paint(out, separator, sep)?;
+        }
+        if self.line_number {
+            color::paint(out, line_number, number)?;
+            color::paint(out, separator, sep)?;
+        }
+        if let (true, Some(column)) = (self.column, column) {
+            color::paint(out, line_number, column)?;
+            color::paint(out, separator, sep)?;
+        }
+        let Some(colors) = colors else {
+            return writeln!(out, "{}", line);
+        };
+        let mut pos = 0;
+        for &(start, end) in found {
+            if start < end {
+                write!(out, "{}", &line[pos..start])?;
+                color::paint(out, Some(&colors.matched), &line[start..end])?;
+                pos = end;
+            }
+        }
+        writeln!(out, "{}", &line[pos..])
+    }
+}
+
+// Searcher 只交给 Printer 被选中的行和需要的上下文行, Printer 根据行号判断是否要输出 `--`
+impl<W: Write> Sink for Printer<W> {
+    fn begin(&mut self, path: Option<&Path>) -> Result<(), crate::Error> {
+        Printer::begin(self, path);
+        Ok(())
+    }
+
+    fn matched(&mut self, line: &SinkLine) -> Result<bool, crate::Error> {
+        self.score = line.score;
+        self.select(line.line_number, line.byte_offset, line.line, line.matches)?;
+        Ok(!self.is_done())
+    }
+
+    fn context(&mut self, line: &SinkLine, _kind: ContextKind) -> Result<bool, crate::Error> {
+        self.context_line(line.line_number, line.byte_offset, line.line)?;
+        Ok(true)
+    }
+
+    fn finish(&mut self, _selected: usize) -> Result<(), crate::Error> {
+        Printer::finish(self)?;
+        Ok(())
+    }
+
+    fn scanned(&mut self, bytes: u64) {
+        self.bytes += bytes;
+    }
+
+    fn binary(&mut self) -> Result<bool, crate::Error> {
+        self.set_binary(true);
+        Ok(true)
+    }
+
+    fn needs_all_matches(&self) -> bool {
+        Printer::needs_all_matches(self)
+    }
+
+    fn needs_score(&self) -> bool {
+        self.ranks()
+    }
+}
+
+/// 和 `str::lines` 一样按行切分, 同时返回每一行行首在 contents 中的字节偏移
+pub fn lines(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    contents.split_inclusive('\n').scan(0, |offset, raw| {
+        let start = *offset;
+        *offset += raw.len();
+        let line = match raw.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => raw,
+        };
+        Some((start, line))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_matches;
+    use crate::searcher::Searcher;
+
+    const CONTENTS: &str = "\
+one
+two match
+three
+four
+five
+six match
+seven
+eight
+nine
+ten match";
+
+    // 逐行交给 printer, 每行只有 find_matches 找到的第一个匹配, 所以 -o 时每行最多输出一个匹配
+    fn render(conf: &Config, path: Option<&Path>) -> String {
+        let mut matches = find_matches(conf, CONTENTS).unwrap().into_iter().peekable();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(conf, &mut out);
+        printer.begin(path);
+        for (index, (offset, line)) in lines(CONTENTS).enumerate() {
+            let number = index + 1;
+            let found = matches.next_if(|m| m.line_number == number);
+            let found = found.map(|m| (m.start, m.end));
+            printer
+                .line(number, offset, line, found.as_slice())
+                .unwrap();
+        }
+        printer.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn config() -> Config {
+        Config {
+            query: String::from("match"),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_lines_with_offsets() {
+        let lines: Vec<_> = lines("ab\r\ncd\n\nef").collect();
+        assert_eq!(lines, vec![(0, "ab"), (4, "cd"), (7, ""), (8, "ef")]);
+    }
+
+    #[test]
+    fn test_line_number_and_column() {
+        let conf = Config {
+            line_number: true,
+            column: true,
+            ..config()
+        };
+        assert_eq!(
+            render(&conf, Some(Path::new("a.txt"))),
+            "a.txt:2:5:two match\na.txt:6:5:six match\na.txt:10:5:ten match\n"
+        );
+    }
+
+    #[test]
+    fn test_context_with_separators() {
+        let conf = Config {
+            line_number: true,
+            before_context: 1,
+            after_context: 1,
+            ..config()
+        };
+        assert_eq!(
+            render(&conf, None),
+            "1-one\n2:two match\n3-three\n--\n5-five\n6:six match\n7-seven\n--\n9-nine\n10:ten match\n"
+        );
+        // 上下文重叠或相邻时合并成一组, 不输出分隔符
+        let conf = Config {
+            before_context: 2,
+            after_context: 2,
+            ..config()
+        };
+        assert_eq!(
+            render(&conf, None),
+            "one\ntwo match\nthree\nfour\nfive\nsix match\nseven\neight\nnine\nten match\n"
+        );
+    }
+
+    #[test]
+    fn test_as_sink() {
+        // Searcher 只交给 Printer 部分行, 输出和 Printer 自己处理每一行完全一样
+        let conf = Config {
+            line_number: true,
+            before_context: 1,
+            after_context: 1,
+            ..config()
+        };
+        let matcher = crate::build_matcher(&conf).unwrap();
+        for conf in [
+            conf.clone(),
+            Config {
+                invert: true,
+                ..conf.clone()
+            },
+            Config {
+                output: OutputMode::FilesWithMatches,
+                ..conf
+            },
+        ] {
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&conf, &mut out);
+            Searcher::from_config(&conf)
+                .search(
+                    &matcher,
+                    CONTENTS.as_bytes(),
+                    Some(Path::new("a.txt")),
+                    &mut printer,
+                )
+                .unwrap();
+            let expected = render(&conf, Some(Path::new("a.txt")));
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_invert_and_count() {
+        let conf = Config {
+            invert: true,
+            output: OutputMode::Count,
+            ..config()
+        };
+        assert_eq!(render(&conf, Some(Path::new("a.txt"))), "a.txt:7\n");
+        let conf = Config {
+            invert: true,
+            after_context: 1,
+            ..config()
+        };
+        assert_eq!(
+            render(&conf, None),
+            "one\ntwo match\nthree\nfour\nfive\nsix match\nseven\neight\nnine\nten match\n"
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let conf = Config {
+            color: ColorChoice::Always,
+            line_number: true,
+            before_context: 1,
+            ..config()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        assert!(printer.needs_all_matches());
+        printer.begin(Some(Path::new("a.txt")));
+        printer.line(1, 0, "before", &[]).unwrap();
+        printer.line(2, 0, "ab ab", &[(0, 2), (3, 5)]).unwrap();
+        printer.finish().unwrap();
+        let expected = "\x1b[35m\x1b[Ka.txt\x1b[m\x1b[K\x1b[36m\x1b[K-\x1b[m\x1b[K\
+                        \x1b[32m\x1b[K1\x1b[m\x1b[K\x1b[36m\x1b[K-\x1b[m\x1b[Kbefore\n\
+                        \x1b[35m\x1b[Ka.txt\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+                        \x1b[32m\x1b[K2\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+                        \x1b[01;31m\x1b[Kab\x1b[m\x1b[K \x1b[01;31m\x1b[Kab\x1b[m\x1b[K\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        // Auto 交给 run 决定, Printer 自己不输出颜色
+        let conf = Config {
+            color: ColorChoice::Auto,
+            ..config()
+        };
+        assert!(!Printer::new(&conf, Vec::new()).needs_all_matches());
+    }
+
+    #[test]
+    fn test_only_matching() {
+        let conf = Config {
+            output: OutputMode::OnlyMatching,
+            line_number: true,
+            column: true,
+            after_context: 1,
+            ..config()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        printer.begin(Some(Path::new("a.txt")));
+        printer.line(1, 0, "no", &[]).unwrap();
+        printer
+            .line(2, 0, "ab ab", &[(0, 2), (3, 5), (5, 5)])
+            .unwrap();
+        printer.line(3, 0, "x", &[]).unwrap();
+        assert_eq!(printer.finish().unwrap(), 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a.txt:2:1:ab\na.txt:2:4:ab\n"
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let conf = Config {
+            replace: Some(Replacement::parse("<$0>").unwrap()),
+            line_number: true,
+            before_context: 1,
+            ..config()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        assert!(printer.needs_all_matches());
+        printer.begin(None);
+        printer.line(1, 0, "match", &[]).unwrap();
+        printer.line(2, 6, "ab ab", &[(0, 2), (3, 5)]).unwrap();
+        printer.finish().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1-match\n2:<ab> <ab>\n");
+
+        let conf = Config {
+            output: OutputMode::OnlyMatching,
+            ..conf
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        printer.begin(None);
+        printer.line(1, 0, "ab ab", &[(0, 2), (3, 5)]).unwrap();
+        printer.finish().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1:<ab>\n1:<ab>\n");
+    }
+
+    #[test]
+    fn test_files_with_and_without_matches() {
+        let render = |output, path: bool| {
+            let conf = Config {
+                output,
+                path: String::from("poem.txt"),
+                ..config()
+            };
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&conf, &mut out);
+            for (name, found) in [("a", true), ("b", false)] {
+                printer.begin(path.then(|| Path::new(name)));
+                printer
+                    .line(1, 0, "line", if found { &[(0, 1)] } else { &[] })
+                    .unwrap();
+                assert_eq!(printer.is_done(), found && output != OutputMode::Count);
+                printer.finish().unwrap();
+            }
+            assert_eq!(printer.total(), 1);
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render(OutputMode::FilesWithMatches, true), "a\n");
+        assert_eq!(render(OutputMode::FilesWithoutMatch, true), "b\n");
+        // 没有 path 时使用 Config.path
+        assert_eq!(render(OutputMode::FilesWithMatches, false), "poem.txt\n");
+        assert_eq!(render(OutputMode::Quiet, true), "");
+        assert_eq!(render(OutputMode::Count, true), "a:1\nb:0\n");
+    }
+
+    #[test]
+    fn test_json_events() {
+        let conf = Config {
+            output: OutputMode::Json,
+            before_context: 1,
+            ..config()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&conf, &mut out);
+        printer.begin(Some(Path::new("a.txt")));
+        printer.line(1, 0, "one", &[]).unwrap();
+        printer.line(2, 4, "two match", &[(4, 9)]).unwrap();
+        printer.finish().unwrap();
+        // 没有输出任何行的文件没有 begin 和 end
+        printer.begin(Some(Path::new("b.txt")));
+        printer.line(1, 0, "nothing", &[]).unwrap();
+        printer.finish().unwrap();
+        printer.summary().unwrap();
+        let expected = [
+            r#"{"type":"begin","path":"a.txt"}"#,
+            r#"{"type":"context","path":"a.txt","line_number":1,"absolute_offset":0,"text":"one","submatches":[]}"#,
+            r#"{"type":"match","path":"a.txt","line_number":2,"absolute_offset":4,"text":"two match","submatches":[{"match":"match","start":4,"end":9}]}"#,
+            r#"{"type":"end","path":"a.txt","matched_lines":1}"#,
+            r#"{"type":"summary","files":2,"files_with_matches":1,"matched_lines":1}"#,
+        ];
+        assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n") + "\n");
+    }
+}
@@ -0,0 +1,861 @@
+//! 一个简单的正则表达式引擎
+//!
+//! 采用 Thompson NFA 的思路: 先把模式解析成语法树, 再编译成指令序列, 最后用 Pike VM 同时模拟所有线程.
+//! 匹配时间和 `模式长度 * 文本长度` 成正比, 不会像回溯引擎那样在 `(a*)*b` 之类的模式上指数爆炸.
+//!
+//! 支持的语法:
+//! - 字面字符, `.` (匹配除换行以外的任意字符)
+//! - 字符类 `[abc]`, `[a-z]`, `[^0-9]`, 以及 `\d \w \s \D \W \S`
+//! - 锚点 `^ $`, 单词边界 `\b \B`. 默认只匹配文本的开头和结尾, 用 [`Regex::with_multi_line`] 编译时也匹配每一行的开头和结尾
+//! - 捕获分组 `(...)`, 非捕获分组 `(?:...)`, 选择 `a|b`
+//! - 重复 `* + ? {n} {n,} {n,m}`, 在后面加 `?` 就是非贪婪版本
+
+use std::cell::RefCell;
+
+use crate::{fold, Error};
+
+// 单个重复次数的上限, 防止 `a{100000}` 这种模式把指令序列撑爆
+const MAX_REPEAT: u32 = 1000;
+
+// 编译后指令数量的上限. 嵌套的重复次数会相乘, 例如 `((a{1000}){1000}){100}`, 只限制单个重复次数是不够的
+const MAX_PROGRAM_LEN: usize = 1 << 20;
+
+thread_local! {
+    // Pike VM 的两个线程列表. 每个线程只分配一次, 之后的每一行都复用, visited 按最长的程序扩容
+    static THREADS: RefCell<(Threads, Threads)> = RefCell::new((Threads::new(), Threads::new()));
+}
+
+/// 编译好的正则表达式
+///
+/// # Example
+/// ```
+/// use pandastd_mini_grep::regex::Regex;
+///
+/// let re = Regex::new(r"(\w+)@(\w+)\.com").unwrap();
+/// assert_eq!(re.find("mail: ferris@rust.com"), Some((6, 21)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    // 捕获槽位数量, 每个分组占用两个槽位 (开始和结束), 第 0 组是整个匹配
+    slots: usize,
+    ignore_case: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        Regex::with_options(pattern, false)
+    }
+
+    /// 和 new 一样, 但可以指定是否忽略大小写
+    pub fn with_options(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+        Regex::build(pattern, ignore_case, false)
+    }
+
+    /// 和 with_options 一样, 但 `^` 和 `$` 也匹配 `\n` 之后和之前的位置, 用于在多行文本中查找.
+    /// `.` 仍然不匹配 `\n`, 跨行需要显式地写出 `\n` 或者 `\s`
+    pub fn with_multi_line(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+        Regex::build(pattern, ignore_case, true)
+    }
+
+    fn build(pattern: &str, ignore_case: bool, multi_line: bool) -> Result<Regex, Error> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let ast = parser.parse()?;
+        let mut compiler = Compiler {
+            prog: Vec::new(),
+            multi_line,
+        };
+        compiler.emit(Inst::Save(0));
+        compiler.compile(&ast)?;
+        compiler.emit(Inst::Save(1));
+        compiler.emit(Inst::Match);
+        Ok(Regex {
+            prog: compiler.prog,
+            slots: (parser.groups + 1) * 2,
+            ignore_case,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// 返回最左边的匹配的字节区间 `(start, end)`
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    /// 从 start 开始查找, 锚点和单词边界仍然会参考 start 之前的字符
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let caps = self.exec(text, start)?;
+        Some((caps[0]?, caps[1]?))
+    }
+
+    /// 返回每个分组的字节区间, 第 0 项是整个匹配, 没有参与匹配的分组为 None
+    pub fn captures(&self, text: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        let caps = self.exec(text, 0)?;
+        Some(
+            caps.chunks(2)
+                .map(|pair| match (pair[0], pair[1]) {
+                    (Some(s), Some(e)) => Some((s, e)),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// 分组数量, 包括代表整个匹配的第 0 组
+    pub fn captures_len(&self) -> usize {
+        self.slots / 2
+    }
+
+    // Pike VM: clist 是当前位置上存活的线程, 按优先级排列, 消耗一个字符后进入 nlist
+    fn exec(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        THREADS.with(|lists| {
+            let (clist, nlist) = &mut *lists.borrow_mut();
+            clist.reset(self.prog.len());
+            nlist.reset(self.prog.len());
+            self.run(clist, nlist, text, start)
+        })
+    }
+
+    fn run(
+        &self,
+        clist: &mut Threads,
+        nlist: &mut Threads,
+        text: &str,
+        start: usize,
+    ) -> Option<Vec<Option<usize>>> {
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            // 还没有找到匹配时, 在每个位置都启动一个新线程, 相当于在模式前面加了 `.*?`
+            // 新线程优先级最低, 所以总是得到最左边的匹配
+            if matched.is_none() {
+                self.add_thread(clist, 0, pos, text, vec![None; self.slots]);
+            }
+            // 已经找到匹配并且没有优先级更高的线程时就可以结束了. 还没有找到时不能提前结束:
+            // 新线程可能只是在这个位置上没有通过 ^ 或 \b 之类的断言, 后面的位置仍然可能匹配
+            if clist.is_empty() && matched.is_some() {
+                break;
+            }
+            let next = text[pos..].chars().next();
+            for (pc, caps) in clist.threads.drain(..) {
+                let ok = match (&self.prog[pc], next) {
+                    (Inst::Match, _) => {
+                        // 后面的线程优先级都更低, 直接丢弃
+                        matched = Some(caps);
+                        break;
+                    }
+                    (Inst::Char(want), Some(c)) => chars_eq(*want, c, self.ignore_case),
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => class.matches(c, self.ignore_case),
+                    _ => false,
+                };
+                if ok {
+                    let next_pos = pos + next.map_or(0, char::len_utf8);
+                    self.add_thread(nlist, pc + 1, next_pos, text, caps);
+                }
+            }
+            clist.clear();
+            match next {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+            std::mem::swap(clist, nlist);
+        }
+        matched
+    }
+
+    // 沿着不消耗字符的指令 (Jmp, Split, Save, Assert) 展开, 把停在消耗字符的指令上的线程放进列表
+    // 用显式的栈代替递归, 避免 `a{0,1000}` 这类模式把调用栈打满
+    fn add_thread(
+        &self,
+        list: &mut Threads,
+        pc: usize,
+        pos: usize,
+        text: &str,
+        caps: Vec<Option<usize>>,
+    ) {
+        let mut stack = vec![(pc, caps)];
+        while let Some((pc, mut caps)) = stack.pop() {
+            if !list.visit(pc) {
+                continue;
+            }
+            match &self.prog[pc] {
+                Inst::Jmp(to) => stack.push((*to, caps)),
+                Inst::Split(first, second) => {
+                    // 后入栈的先处理, 所以先压入优先级低的分支
+                    stack.push((*second, caps.clone()));
+                    stack.push((*first, caps));
+                }
+                Inst::Save(slot) => {
+                    caps[*slot] = Some(pos);
+                    stack.push((pc + 1, caps));
+                }
+                Inst::Assert(look) => {
+                    if look.holds(text, pos) {
+                        stack.push((pc + 1, caps));
+                    }
+                }
+                _ => list.threads.push((pc, caps)),
+            }
+        }
+    }
+}
+
+// 忽略大小写时按大小写折叠比较, 正则是逐个字符匹配的, 所以 `ß` 不会匹配 `ss`
+fn chars_eq(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || (ignore_case && fold::chars_eq(a, b))
+}
+
+/// `\w` 和 `\b` 使用的单词字符: Unicode 字母, 数字和下划线
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// 一个位置上的线程列表, 同一条指令只保留优先级最高的线程.
+// visited 记录每条指令最后一次展开时的 generation, 等于当前值就是本轮已经展开过的,
+// 所以进入下一个位置时只要把 generation 加一, 不用清空整个数组
+struct Threads {
+    threads: Vec<(usize, Vec<Option<usize>>)>,
+    visited: Vec<u32>,
+    generation: u32,
+}
+
+impl Threads {
+    fn new() -> Threads {
+        Threads {
+            threads: Vec::new(),
+            visited: Vec::new(),
+            generation: 1,
+        }
+    }
+
+    // 开始一次新的搜索, 程序有 len 条指令
+    fn reset(&mut self, len: usize) {
+        self.clear();
+        if self.visited.len() < len {
+            self.visited.resize(len, 0);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    // 第一次访问返回 true
+    fn visit(&mut self, pc: usize) -> bool {
+        std::mem::replace(&mut self.visited[pc], self.generation) != self.generation
+    }
+
+    fn clear(&mut self) {
+        self.threads.clear();
+        self.generation = self.generation.wrapping_add(1);
+        // 回绕后旧的标记可能和新的 generation 相等, 这时才真的清空一次
+        if self.generation == 0 {
+            self.visited.fill(0);
+            self.generation = 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Look),
+    Save(usize),
+    // 两个分支都会执行, 第一个分支优先级更高
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Look {
+    Start,
+    End,
+    // 多行模式下的 ^ 和 $
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Look {
+    fn holds(&self, text: &str, pos: usize) -> bool {
+        let before = text[..pos].chars().next_back();
+        let after = text[pos..].chars().next();
+        let boundary = before.is_some_and(is_word_char) != after.is_some_and(is_word_char);
+        match self {
+            Look::Start => pos == 0,
+            Look::End => pos == text.len(),
+            Look::LineStart => before.is_none_or(|c| c == '\n'),
+            Look::LineEnd => after.is_none_or(|c| c == '\n'),
+            Look::WordBoundary => boundary,
+            Look::NotWordBoundary => !boundary,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    // bool 表示是否取反, 例如 \D
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let mut hit = self.contains(c);
+        if !hit && ignore_case {
+            hit = c.to_lowercase().any(|l| self.contains(l))
+                || c.to_uppercase().any(|u| self.contains(u));
+        }
+        hit != self.negated
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match item {
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != *negated,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    // Some(index) 是捕获分组, None 是非捕获分组
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+fn syntax_error(msg: String) -> Error {
+    Error::Pattern(msg)
+}
+
+// 递归下降解析器, 优先级从低到高依次是: 选择 | , 连接, 重复, 原子
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Node, Error> {
+        let node = self.parse_alternate()?;
+        if self.pos < self.chars.len() {
+            // 只有多余的 `)` 才会走到这里
+            return Err(syntax_error(format!(
+                "unmatched ')' at position {}",
+                self.pos
+            )));
+        }
+        Ok(node)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternate(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.parse_repeat()?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, Error> {
+        let mut node = self.parse_atom()?;
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                // parse_counts 会自己消耗整个 {n,m}
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => {
+                        node = self.repeat(node, counts);
+                        continue;
+                    }
+                    None => break,
+                },
+                _ => break,
+            };
+            self.pos += 1;
+            node = self.repeat(node, (min, max));
+        }
+        Ok(node)
+    }
+
+    // 量词后面紧跟的 `?` 表示非贪婪
+    fn repeat(&mut self, node: Node, (min, max): (u32, Option<u32>)) -> Node {
+        let greedy = !self.eat('?');
+        Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        }
+    }
+
+    // 解析 {n}, {n,}, {n,m}, 不是合法量词时返回 None, 此时 `{` 按普通字符处理
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.parse_number();
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            min
+        };
+        let (Some(min), true) = (min, self.eat('}')) else {
+            self.pos = start;
+            return Ok(None);
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(syntax_error(format!(
+                "invalid repetition {{{},{}}}",
+                min,
+                max.unwrap()
+            )));
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(syntax_error(format!(
+                "repetition count exceeds {}",
+                MAX_REPEAT
+            )));
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, Error> {
+        let pos = self.pos;
+        let c = self.next().expect("parse_atom called at end of pattern");
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Look(Look::Start),
+            '$' => Node::Look(Look::End),
+            '[' => Node::Class(self.parse_class()?),
+            '(' => self.parse_group()?,
+            '*' | '+' | '?' => {
+                return Err(syntax_error(format!(
+                    "nothing to repeat at position {}",
+                    pos
+                )))
+            }
+            '\\' => self.parse_escape()?,
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_group(&mut self) -> Result<Node, Error> {
+        let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+            self.pos += 2;
+            None
+        } else {
+            self.groups += 1;
+            Some(self.groups)
+        };
+        let node = self.parse_alternate()?;
+        if !self.eat(')') {
+            return Err(syntax_error("unclosed group, missing ')'".to_string()));
+        }
+        Ok(Node::Group(Box::new(node), index))
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, Error> {
+        let c = self
+            .next()
+            .ok_or_else(|| syntax_error("trailing '\\' in pattern".to_string()))?;
+        Ok(match c {
+            'b' => Node::Look(Look::WordBoundary),
+            'B' => Node::Look(Look::NotWordBoundary),
+            _ => match self.escape_item(c)? {
+                ClassItem::Range(c, _) => Node::Char(c),
+                item => Node::Class(Class {
+                    items: vec![item],
+                    negated: false,
+                }),
+            },
+        })
+    }
+
+    // 字符类内外共用的转义, 返回单个字符 (用 Range(c, c) 表示) 或者 Perl 字符类
+    fn escape_item(&mut self, c: char) -> Result<ClassItem, Error> {
+        Ok(match c {
+            'd' => ClassItem::Perl(Perl::Digit, false),
+            'D' => ClassItem::Perl(Perl::Digit, true),
+            'w' => ClassItem::Perl(Perl::Word, false),
+            'W' => ClassItem::Perl(Perl::Word, true),
+            's' => ClassItem::Perl(Perl::Space, false),
+            'S' => ClassItem::Perl(Perl::Space, true),
+            'n' => ClassItem::Range('\n', '\n'),
+            't' => ClassItem::Range('\t', '\t'),
+            'r' => ClassItem::Range('\r', '\r'),
+            c if c.is_alphanumeric() => {
+                return Err(syntax_error(format!("unknown escape '\\{}'", c)))
+            }
+            // 标点符号转义后就是它本身, 例如 \. \* \\
+            c => ClassItem::Range(c, c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, Error> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        // `]` 出现在最前面时按普通字符处理, 例如 `[]a]`
+        let mut first = true;
+        loop {
+            let c = self
+                .next()
+                .ok_or_else(|| syntax_error("unclosed character class, missing ']'".to_string()))?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                let e = self
+                    .next()
+                    .ok_or_else(|| syntax_error("trailing '\\' in pattern".to_string()))?;
+                match self.escape_item(e)? {
+                    ClassItem::Range(c, _) => c,
+                    item => {
+                        items.push(item);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+            // `a-z` 是区间, 末尾的 `-` 按普通字符处理
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let mut hi = self.next().unwrap();
+                if hi == '\\' {
+                    let e = self
+                        .next()
+                        .ok_or_else(|| syntax_error("trailing '\\' in pattern".to_string()))?;
+                    hi = match self.escape_item(e)? {
+                        ClassItem::Range(c, _) => c,
+                        _ => return Err(syntax_error("invalid range end in class".to_string())),
+                    };
+                }
+                if hi < lo {
+                    return Err(syntax_error(format!("invalid class range {}-{}", lo, hi)));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Class { items, negated })
+    }
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+    // 是否把 ^ 和 $ 编译成行首和行尾
+    multi_line: bool,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> usize {
+        self.prog.push(inst);
+        self.prog.len() - 1
+    }
+
+    // 先占位, 等知道跳转目标后再回填
+    fn patch(&mut self, at: usize, inst: Inst) {
+        self.prog[at] = inst;
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), Error> {
+        // 每次进入 compile 之前最多只额外生成几条指令, 所以在这里检查就能限制总的大小
+        if self.prog.len() > MAX_PROGRAM_LEN {
+            return Err(syntax_error(format!(
+                "pattern is too large, it compiles to more than {} instructions",
+                MAX_PROGRAM_LEN
+            )));
+        }
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.emit(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.emit(Inst::Any);
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()));
+            }
+            Node::Look(look) => {
+                let look = match look {
+                    Look::Start if self.multi_line => Look::LineStart,
+                    Look::End if self.multi_line => Look::LineEnd,
+                    look => *look,
+                };
+                self.emit(Inst::Assert(look));
+            }
+            Node::Group(node, index) => match index {
+                Some(i) => {
+                    self.emit(Inst::Save(i * 2));
+                    self.compile(node)?;
+                    self.emit(Inst::Save(i * 2 + 1));
+                }
+                None => self.compile(node)?,
+            },
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternate(branches) => {
+                // split L1, next; L1: a; jmp end; next: split L2, ...; 最后一个分支不需要 split
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.emit(Inst::Match);
+                        self.compile(branch)?;
+                        jumps.push(self.emit(Inst::Match));
+                        let next = self.prog.len();
+                        self.patch(split, Inst::Split(split + 1, next));
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+                let end = self.prog.len();
+                for jump in jumps {
+                    self.patch(jump, Inst::Jmp(end));
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    // L: split body, end; body; jmp L
+                    None => {
+                        let split = self.emit(Inst::Match);
+                        self.compile(node)?;
+                        self.emit(Inst::Jmp(split));
+                        let end = self.prog.len();
+                        self.patch(split, self.split(split + 1, end, *greedy));
+                    }
+                    // 每一个可选的副本都是 split body, end
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Match));
+                            self.compile(node)?;
+                        }
+                        let end = self.prog.len();
+                        for split in splits {
+                            self.patch(split, self.split(split + 1, end, *greedy));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 贪婪时优先进入循环体, 非贪婪时优先跳过
+    fn split(&self, body: usize, skip: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(body, skip)
+        } else {
+            Inst::Split(skip, body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern).unwrap().find(text)
+    }
+
+    #[test]
+    fn test_literal_and_anchors() {
+        assert_eq!(find("fast", "safe, fast, productive."), Some((6, 10)));
+        assert_eq!(find("^safe", "safe, fast"), Some((0, 4)));
+        assert_eq!(find("^fast", "safe, fast"), None);
+        assert_eq!(find("fast$", "safe, fast"), Some((6, 10)));
+        assert_eq!(find("", "abc"), Some((0, 0)));
+        assert_eq!(find("^b", "a\nb"), None);
+        let re = Regex::with_multi_line(r"^b$\n^c", false).unwrap();
+        assert_eq!(re.find("a\nb\nc"), Some((2, 5)));
+        assert_eq!(re.find("ab\nc"), None);
+    }
+
+    #[test]
+    fn test_classes_and_repetition() {
+        assert_eq!(find(r"\d+", "abc 2023 x"), Some((4, 8)));
+        assert_eq!(find("[a-c]{2,3}", "xxabcabc"), Some((2, 5)));
+        assert_eq!(find("[^a-z ]+", "abc DEF"), Some((4, 7)));
+        assert_eq!(find("colou?r", "the color red"), Some((4, 9)));
+        assert_eq!(find("a{3}", "aa aaaa"), Some((3, 6)));
+        assert_eq!(find("<.+>", "<a><b>"), Some((0, 6)));
+        assert_eq!(find("<.+?>", "<a><b>"), Some((0, 3)));
+        assert_eq!(find(r"\bduct\b", "safe, fast, productive."), None);
+        assert_eq!(find(r"\bduct", "productive duct"), Some((11, 15)));
+        assert_eq!(find("x{,", "ax{,"), Some((1, 4)));
+    }
+
+    #[test]
+    fn test_alternation_and_groups() {
+        let re = Regex::new(r"(\w+)@(\w+)\.(com|org)").unwrap();
+        let caps = re.captures("mail ferris@rust.org now").unwrap();
+        assert_eq!(caps.len(), re.captures_len());
+        assert_eq!(caps[0], Some((5, 20)));
+        assert_eq!(caps[1], Some((5, 11)));
+        assert_eq!(caps[2], Some((12, 16)));
+        assert_eq!(caps[3], Some((17, 20)));
+        assert_eq!(find("cat|dog", "hotdog"), Some((3, 6)));
+        assert_eq!(find("(?:ab)+", "xababa"), Some((1, 5)));
+        assert!(Regex::new("(a|)").unwrap().captures("b").is_some());
+    }
+
+    #[test]
+    fn test_ignore_case_and_unicode() {
+        let re = Regex::with_options("RUST", true).unwrap();
+        assert_eq!(re.find("I love rust"), Some((7, 11)));
+        let re = Regex::with_options("[a-c]+", true).unwrap();
+        assert_eq!(re.find("xABC"), Some((1, 4)));
+        assert_eq!(find("世.", "你好世界"), Some((6, 12)));
+        // 嵌套的空循环不会卡死, 也不会指数爆炸
+        assert!(Regex::new("(a*)*b")
+            .unwrap()
+            .find(&"a".repeat(200))
+            .is_none());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for pattern in [
+            "(abc", "abc)", "[abc", "*a", r"a\", "a{3,1}", r"\q", "[z-a]",
+        ] {
+            assert!(
+                Regex::new(pattern).is_err(),
+                "{} should not compile",
+                pattern
+            );
+        }
+        // 嵌套的重复次数相乘之后超过上限, 返回错误而不是耗尽内存
+        let err = Regex::new("((a{1000}){1000}){100}").unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+        assert!(Regex::new("(a{1000}){1000}").is_ok());
+    }
+
+    #[test]
+    fn test_threads_reuse() {
+        // 线程列表在不同长度的程序之间复用, 结果不受上一次搜索的影响
+        let long = Regex::new("a{50}b").unwrap();
+        let short = Regex::new("ab").unwrap();
+        assert_eq!(long.find(&"a".repeat(60)), None);
+        assert_eq!(short.find("xab"), Some((1, 3)));
+        assert_eq!(long.find(&("a".repeat(50) + "b")), Some((0, 51)));
+        // generation 回绕时清空旧的标记
+        let mut threads = Threads::new();
+        threads.reset(2);
+        threads.generation = u32::MAX;
+        assert!(threads.visit(0));
+        threads.clear();
+        assert_eq!(threads.generation, 1);
+        assert!(threads.visit(0));
+        assert!(!threads.visit(0));
+    }
+}
@@ -0,0 +1,172 @@
+//! `--replace` 和 `--in-place` 使用的替换
+//!
+//! 替换字符串中 `$0` 表示整个匹配, `$$` 表示 `$` 本身, 其他字符原样输出.
+//! `$` 后面跟着其他字符是错误, 避免 `$1` 之类的写法被悄悄当成普通文本.
+//!
+//! `--in-place` 先把替换后的内容写到同一个目录下的临时文件, 再用 rename 覆盖原文件,
+//! 所以任何时候原文件要么是旧的内容, 要么是完整的新内容.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::Error;
+
+/// 解析好的替换字符串
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    // $0
+    Matched,
+}
+
+impl Replacement {
+    pub fn parse(spec: &str) -> Result<Replacement, Error> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = spec.chars();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('$') => literal.push('$'),
+                Some('0') => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Matched);
+                }
+                other => {
+                    return Err(Error::Args(format!(
+                        "invalid replacement '{}': unsupported '${}', use $0 for the match or $$ for a literal $",
+                        spec,
+                        other.map(String::from).unwrap_or_default()
+                    )))
+                }
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Replacement { parts })
+    }
+
+    /// 把一个匹配替换后的文本追加到 out
+    pub fn expand(&self, matched: &str, out: &mut String) {
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Matched => out.push_str(matched),
+            }
+        }
+    }
+
+    /// 把 line 中 found 的每个区间都替换掉, 返回替换后的行, 以及替换出的文本在其中的区间
+    pub fn apply(&self, line: &str, found: &[(usize, usize)]) -> (String, Vec<(usize, usize)>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut spans = Vec::with_capacity(found.len());
+        let mut pos = 0;
+        for &(start, end) in found {
+            replaced.push_str(&line[pos..start]);
+            let from = replaced.len();
+            self.expand(&line[start..end], &mut replaced);
+            spans.push((from, replaced.len()));
+            pos = end;
+        }
+        replaced.push_str(&line[pos..]);
+        (replaced, spans)
+    }
+}
+
+/// 用 contents 原子地替换 path 的内容, 保留原文件的权限.
+/// backup 不为 None 时, 先把原文件复制到 path 加上这个后缀的位置.
+/// path 是符号链接时修改的是它指向的文件, 备份也放在那个文件旁边, 链接本身保持不变
+pub fn write_atomic(path: &Path, contents: &[u8], backup: Option<&str>) -> io::Result<()> {
+    // 直接 rename 到链接上会把链接换成普通文件, 真正的文件却没有改
+    let path = &fs::canonicalize(path)?;
+    let permissions = fs::metadata(path)?.permissions();
+    // 临时文件必须和原文件在同一个目录, rename 才是原子的
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".mini_grep-{}.tmp", process::id()));
+    let tmp = path.with_file_name(name);
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::set_permissions(&tmp, permissions)?;
+        if let Some(suffix) = backup {
+            fs::copy(path, backup_path(path, suffix))?;
+        }
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// 备份文件的路径, 例如 `src/main.rs` 加上 `.bak` 是 `src/main.rs.bak`
+pub fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_parse_and_apply() {
+        let replacement = Replacement::parse("[$0] costs $$5").unwrap();
+        let (line, spans) = replacement.apply("a fast car", &[(2, 6)]);
+        assert_eq!(line, "a [fast] costs $5 car");
+        assert_eq!(spans, vec![(2, 17)]);
+        // 空匹配相当于插入
+        let (line, _) = Replacement::parse("> ").unwrap().apply("quote", &[(0, 0)]);
+        assert_eq!(line, "> quote");
+        let (line, spans) = Replacement::parse("")
+            .unwrap()
+            .apply("a-b-c", &[(1, 2), (3, 4)]);
+        assert_eq!((line.as_str(), spans), ("abc", vec![(1, 1), (2, 2)]));
+        assert!(Replacement::parse("$1").is_err());
+        assert!(Replacement::parse("cost $").is_err());
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = env::temp_dir().join(format!("mini_grep_replace_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("poem.txt");
+        fs::write(&path, "old").unwrap();
+        write_atomic(&path, b"new", Some(".bak")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("poem.txt.bak")).unwrap(), "old");
+        // 没有留下临时文件
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // 通过符号链接修改时, 链接仍然是链接, 改的是它指向的文件
+        #[cfg(unix)]
+        {
+            let link = dir.join("link.txt");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            write_atomic(&link, b"newer", None).unwrap();
+            assert!(fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            assert_eq!(fs::read_to_string(&path).unwrap(), "newer");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,761 @@
+//! 把搜索引擎嵌入到其他程序中使用
+//!
+//! [`Searcher`] 从任意 [`Read`] 或 [`BufRead`] 中逐行读取, 用任意 [`Matcher`] 查找, 把被选中的行和上下文行交给 [`Sink`].
+//! 它不输出任何东西, 所以不需要像 [`crate::run`] 那样捕获标准输出.
+//! 命令行也是用 Searcher 把结果交给 [`crate::printer::Printer`] (它实现了 Sink) 输出的,
+//! 所以 -z 解压, 二进制输入的检测和 -U 的行为都和命令行完全一样. 只有 [`Read`] 时用 [`Searcher::search_read`], 它会用 [`BufReader`] 包一层.
+//!
+//! 和命令行一样, 不是合法 UTF-8 的字节会被替换成 U+FFFD, 行尾的 `\n` 或 `\r\n` 不属于行的内容.
+//!
+//! # Example
+//! ```
+//! use pandastd_mini_grep::searcher::{Searcher, Sink, SinkLine};
+//! use pandastd_mini_grep::{build_matcher, Config, Error};
+//!
+//! // 只收集被选中的行的行号和第一个匹配
+//! struct Collect(Vec<(usize, String)>);
+//!
+//! impl Sink for Collect {
+//!     fn matched(&mut self, line: &SinkLine) -> Result<bool, Error> {
+//!         let (start, end) = line.matches[0];
+//!         self.0.push((line.line_number, line.line[start..end].to_string()));
+//!         Ok(true)
+//!     }
+//! }
+//!
+//! let conf = Config {
+//!     query: String::from("FAST"),
+//!     ignore_case: true,
+//!     ..Config::default()
+//! };
+//! let matcher = build_matcher(&conf).unwrap();
+//! let mut sink = Collect(Vec::new());
+//! let contents = "Rust:\nsafe, fast, productive.\nPick three.";
+//! let selected = Searcher::from_config(&conf)
+//!     .search(&matcher, contents.as_bytes(), None, &mut sink)
+//!     .unwrap();
+//! assert_eq!(selected, 1);
+//! assert_eq!(sink.0, vec![(2, String::from("fast"))]);
+//! ```
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::config::BinaryFiles;
+use crate::literal::{self, Horspool};
+use crate::matcher::Matcher;
+use crate::{inflate, printer, walk, Config, Error, OutputMode};
+
+/// 交给 [`Sink`] 的一行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkLine<'a> {
+    /// 行号, 从 1 开始
+    pub line_number: usize,
+    /// 行首在输入中的字节偏移
+    pub byte_offset: usize,
+    /// 整行内容, 不包含换行符
+    pub line: &'a str,
+    /// 行内所有匹配的字节区间, 按位置排列. -v 时被选中的行没有匹配, 这里为空.
+    /// [`Sink::needs_all_matches`] 返回 false 时只有第一个匹配
+    pub matches: &'a [(usize, usize)],
+    /// 被选中的行的分数, 见 [`Matcher::score`]. 只有 [`Sink::needs_score`] 返回 true 时才计算, 否则是 0
+    pub score: usize,
+}
+
+/// 上下文行在被选中的行之前还是之后
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextKind {
+    Before,
+    After,
+}
+
+/// 接收搜索结果
+///
+/// 每个输入先调用一次 begin, 然后按行号顺序调用 matched 和 context, 最后调用一次 finish.
+/// matched, context 和 binary 返回 `Ok(false)` 时, 剩下的输入不再读取, 但仍然会调用 finish.
+/// 返回的错误会原样从 [`Searcher::search`] 返回
+pub trait Sink {
+    /// 开始一个输入, path 是 [`Searcher::search`] 收到的路径
+    fn begin(&mut self, _path: Option<&Path>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// 一个被选中的行
+    fn matched(&mut self, line: &SinkLine) -> Result<bool, Error>;
+
+    /// 一个上下文行, 只有 [`Searcher`] 的 before_context / after_context 不为 0 时才会有.
+    /// 两组上下文之间是否相邻, 可以通过行号判断
+    fn context(&mut self, _line: &SinkLine, _kind: ContextKind) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    /// 输入结束, selected 是被选中的行数
+    fn finish(&mut self, _selected: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// 在 finish 之前调用, bytes 是搜索过的字节数. -z 时是解压之后的字节数, 提前结束时没有读到的部分不算
+    fn scanned(&mut self, _bytes: u64) {}
+
+    /// 输入的开头有 NUL 字节, 并且 [`Searcher::binary_files`] 是 [`BinaryFiles::Binary`] 时,
+    /// 在交给 sink 任何行之前调用. 默认照常搜索
+    fn binary(&mut self) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    /// 是否需要行内所有的匹配, 返回 false 时 Searcher 找到第一个匹配就停下
+    fn needs_all_matches(&self) -> bool {
+        true
+    }
+
+    /// 是否需要被选中的行的分数, 例如按分数排序输出时
+    fn needs_score(&self) -> bool {
+        false
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn begin(&mut self, path: Option<&Path>) -> Result<(), Error> {
+        (**self).begin(path)
+    }
+
+    fn matched(&mut self, line: &SinkLine) -> Result<bool, Error> {
+        (**self).matched(line)
+    }
+
+    fn context(&mut self, line: &SinkLine, kind: ContextKind) -> Result<bool, Error> {
+        (**self).context(line, kind)
+    }
+
+    fn finish(&mut self, selected: usize) -> Result<(), Error> {
+        (**self).finish(selected)
+    }
+
+    fn scanned(&mut self, bytes: u64) {
+        (**self).scanned(bytes)
+    }
+
+    fn binary(&mut self) -> Result<bool, Error> {
+        (**self).binary()
+    }
+
+    fn needs_all_matches(&self) -> bool {
+        (**self).needs_all_matches()
+    }
+
+    fn needs_score(&self) -> bool {
+        (**self).needs_score()
+    }
+}
+
+// 暂存的前置上下文: (行号, 行首偏移, 内容, 匹配)
+type Pending = (usize, usize, String, Vec<(usize, usize)>);
+
+/// 逐行搜索一个输入
+///
+/// 只决定哪些行被选中, 以及需要哪些上下文行; 怎样使用这些行由 [`Sink`] 决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Searcher {
+    /// 选中不匹配的行, 和 -v 一样
+    pub invert: bool,
+    /// 被选中的行之前 / 之后额外交给 Sink 的上下文行数
+    pub before_context: usize,
+    pub after_context: usize,
+    /// 怎样处理开头有 NUL 字节的输入, 和 --binary-files 一样
+    pub binary_files: BinaryFiles,
+    /// 开头是 gzip 或 zlib 数据的输入先解压再搜索, 和 -z 一样
+    pub decompress: bool,
+    /// 匹配可以跨行, 和 -U 一样. 这时整个输入会被读进内存
+    pub multiline: bool,
+}
+
+impl Searcher {
+    /// 使用 conf 中对应的选项. 输出方式不会输出上下文行时 (例如 -c), 不需要上下文
+    pub fn from_config(conf: &Config) -> Searcher {
+        let context = [OutputMode::Lines, OutputMode::Json].contains(&conf.output);
+        Searcher {
+            invert: conf.invert,
+            before_context: if context { conf.before_context } else { 0 },
+            after_context: if context { conf.after_context } else { 0 },
+            binary_files: conf.binary_files,
+            decompress: conf.decompress,
+            multiline: conf.multiline,
+        }
+    }
+
+    /// 搜索 reader 中的所有行, 返回被选中的行数
+    ///
+    /// path 只用于 [`Sink::begin`] 和错误信息: 不为 None 时读取失败返回 [`Error::File`], 否则返回 [`Error::Io`]
+    pub fn search<M, R, S>(
+        &self,
+        matcher: &M,
+        reader: R,
+        path: Option<&Path>,
+        sink: S,
+    ) -> Result<usize, Error>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        S: Sink,
+    {
+        if !self.decompress {
+            return self.search_decoded(matcher, reader, path, sink);
+        }
+        let reader = inflate::auto(reader).map_err(|err| read_error(path, err))?;
+        self.search_decoded(matcher, reader, path, sink)
+    }
+
+    /// 和 search 一样, 但 reader 只需要实现 [`Read`], 这里用读缓冲区包一层.
+    /// 已经带缓冲区的输入 (例如 [`BufReader`] 或 `&[u8]`) 直接交给 search, 省掉一次复制
+    pub fn search_read<M, R, S>(
+        &self,
+        matcher: &M,
+        reader: R,
+        path: Option<&Path>,
+        sink: S,
+    ) -> Result<usize, Error>
+    where
+        M: Matcher + ?Sized,
+        R: Read,
+        S: Sink,
+    {
+        let reader = BufReader::with_capacity(crate::READ_BUFFER_SIZE, reader);
+        self.search(matcher, reader, path, sink)
+    }
+
+    /// 打开并搜索 path 指向的文件
+    pub fn search_path<M, S>(&self, matcher: &M, path: &Path, sink: S) -> Result<usize, Error>
+    where
+        M: Matcher + ?Sized,
+        S: Sink,
+    {
+        let file = File::open(path).map_err(|e| Error::file(path, e))?;
+        self.search_read(matcher, file, Some(path), sink)
+    }
+
+    // 先检查读缓冲区中开头的一段有没有 NUL 字节, 是二进制输入时按 binary_files 处理, 再逐行搜索
+    fn search_decoded<M, R, S>(
+        &self,
+        matcher: &M,
+        reader: R,
+        path: Option<&Path>,
+        mut sink: S,
+    ) -> Result<usize, Error>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        S: Sink,
+    {
+        let mut reader = Counted {
+            inner: reader,
+            count: 0,
+        };
+        sink.begin(path)?;
+        let head = reader.fill_buf().map_err(|err| read_error(path, err))?;
+        let binary = walk::is_binary(&head[..head.len().min(walk::BINARY_SNIFF_LEN)]);
+        let more = match self.binary_files {
+            BinaryFiles::Binary if binary => sink.binary()?,
+            // 当作没有匹配的输入: -c 输出 0, -L 会列出它
+            BinaryFiles::WithoutMatch if binary => false,
+            _ => true,
+        };
+        let mut lines = Lines {
+            searcher: self,
+            sink,
+            path,
+            context: Context::new(self.invert, self.before_context, self.after_context),
+        };
+        if more {
+            if self.multiline {
+                lines.search_multiline(matcher, &mut reader)?;
+            } else {
+                match matcher.as_literal() {
+                    Some(finder) if lines.fast_path(finder) => {
+                        lines.search_chunks(finder, &mut reader)?
+                    }
+                    _ => lines.search_lines(matcher, &mut reader)?,
+                }
+            }
+        }
+        let selected = lines.context.selected;
+        lines.sink.scanned(reader.count);
+        lines.sink.finish(selected)?;
+        Ok(selected)
+    }
+}
+
+// 统计从 inner 中读取了多少字节, 用于 Sink::scanned. 逐行读取时通过 consume, read_to_end 时通过 read
+struct Counted<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+fn read_error(path: Option<&Path>, err: io::Error) -> Error {
+    match path {
+        Some(path) => Error::file(path, err),
+        None => Error::Io(err),
+    }
+}
+
+/// 决定每一行是被选中的行, 上下文行还是直接丢掉, 再交给 sink.
+/// Searcher 和 [`crate::printer::Printer::line`] 都通过它处理上下文, 所以两者的行为完全一样
+#[derive(Debug, Default)]
+pub(crate) struct Context {
+    invert: bool,
+    before: usize,
+    after: usize,
+    // 还没有交给 sink 的前置上下文, 最多保留 before 行
+    pending: VecDeque<Pending>,
+    after_left: usize,
+    // 被选中的行数
+    selected: usize,
+}
+
+impl Context {
+    pub(crate) fn new(invert: bool, before: usize, after: usize) -> Context {
+        Context {
+            invert,
+            before,
+            after,
+            ..Context::default()
+        }
+    }
+
+    /// 开始一个新的输入
+    pub(crate) fn reset(&mut self) {
+        self.pending.clear();
+        self.after_left = 0;
+        self.selected = 0;
+    }
+
+    /// 处理一行, sink 要求停止时返回 false. score 只在这一行被选中并且 sink 需要时才调用
+    pub(crate) fn line<S: Sink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+        number: usize,
+        offset: usize,
+        line: &str,
+        found: &[(usize, usize)],
+        score: impl FnOnce() -> usize,
+    ) -> Result<bool, Error> {
+        let event = SinkLine {
+            line_number: number,
+            byte_offset: offset,
+            line,
+            matches: found,
+            score: 0,
+        };
+        if found.is_empty() == self.invert {
+            self.selected += 1;
+            if !self.flush(sink)? {
+                return Ok(false);
+            }
+            self.after_left = self.after;
+            let score = if sink.needs_score() { score() } else { 0 };
+            sink.matched(&SinkLine { score, ..event })
+        } else if self.after_left > 0 {
+            self.after_left -= 1;
+            sink.context(&event, ContextKind::After)
+        } else {
+            if self.before > 0 {
+                if self.pending.len() == self.before {
+                    self.pending.pop_front();
+                }
+                self.pending
+                    .push_back((number, offset, line.to_string(), found.to_vec()));
+            }
+            Ok(true)
+        }
+    }
+
+    // 把暂存的前置上下文交给 sink, sink 要求停止时返回 false
+    fn flush<S: Sink + ?Sized>(&mut self, sink: &mut S) -> Result<bool, Error> {
+        while let Some((line_number, byte_offset, line, matches)) = self.pending.pop_front() {
+            let event = SinkLine {
+                line_number,
+                byte_offset,
+                line: &line,
+                matches: &matches,
+                score: 0,
+            };
+            if !sink.context(&event, ContextKind::Before)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+// 一个输入的搜索状态, 不管用哪种方式读出来的行都交给 context
+struct Lines<'a, S> {
+    searcher: &'a Searcher,
+    sink: S,
+    path: Option<&'a Path>,
+    context: Context,
+}
+
+impl<S: Sink> Lines<'_, S> {
+    fn line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        found: &[(usize, usize)],
+        score: impl FnOnce() -> usize,
+    ) -> Result<bool, Error> {
+        self.context
+            .line(&mut self.sink, number, offset, line, found, score)
+    }
+
+    fn read_error(&self, err: io::Error) -> Error {
+        read_error(self.path, err)
+    }
+
+    // 字面匹配并且不需要上下文, 也不反转时, 不匹配的行根本不用交给 sink, 可以按块查找
+    fn fast_path(&self, finder: &Horspool) -> bool {
+        let searcher = self.searcher;
+        let every_line =
+            searcher.invert || searcher.before_context > 0 || searcher.after_context > 0;
+        let needle = finder.needle();
+        !every_line
+            && !self.sink.needs_all_matches()
+            && !needle.is_empty()
+            && !needle.contains(&b'\n')
+    }
+
+    // 无论输入多大, 同一时间只有一行在内存中
+    fn search_lines<M: Matcher + ?Sized, R: BufRead>(
+        &mut self,
+        matcher: &M,
+        mut reader: R,
+    ) -> Result<(), Error> {
+        let all = self.sink.needs_all_matches();
+        let mut buf = Vec::new();
+        let mut found = Vec::new();
+        let mut number = 0;
+        let mut offset = 0;
+        // read_until 不会校验 UTF-8, 所以可以先读出原始字节, 再有损地解码
+        loop {
+            buf.clear();
+            let len = reader
+                .read_until(b'\n', &mut buf)
+                .map_err(|err| self.read_error(err))?;
+            if len == 0 {
+                return Ok(());
+            }
+            number += 1;
+            if buf.ends_with(b"\n") {
+                buf.pop();
+                if buf.ends_with(b"\r") {
+                    buf.pop();
+                }
+            }
+            let line = String::from_utf8_lossy(&buf);
+            matcher.find_into(&line, all, &mut found);
+            if !self.line(number, offset, &line, &found, || {
+                matcher.score(&line, &found)
+            })? {
+                return Ok(());
+            }
+            offset += len;
+        }
+    }
+
+    // 按块读取, 用 Horspool 在整块上查找, 只把命中的行切出来, 其余的行只统计换行符的数量
+    fn search_chunks<R: BufRead>(&mut self, finder: &Horspool, mut reader: R) -> Result<(), Error> {
+        let mut chunk = Vec::new();
+        let mut lines_before = 0;
+        // chunk 开头在输入中的字节偏移
+        let mut offset = 0;
+        loop {
+            let data = reader.fill_buf().map_err(|err| self.read_error(err))?;
+            if data.is_empty() {
+                break;
+            }
+            // 只处理到最后一个换行符为止, 后面不完整的一行留给下一块
+            let newline = data.iter().rposition(|&b| b == b'\n');
+            let used = newline.map_or(data.len(), |i| i + 1);
+            let more = if newline.is_some() && chunk.is_empty() {
+                // 没有上一块剩下的半行时, 直接在读缓冲区 (或者映射的内存) 中查找, 不用复制
+                let more = self.search_chunk(finder, &data[..used], offset, &mut lines_before)?;
+                offset += used;
+                more
+            } else {
+                chunk.extend_from_slice(&data[..used]);
+                if newline.is_some() {
+                    let more = self.search_chunk(finder, &chunk, offset, &mut lines_before)?;
+                    offset += chunk.len();
+                    chunk.clear();
+                    more
+                } else {
+                    true
+                }
+            };
+            reader.consume(used);
+            if !more {
+                return Ok(());
+            }
+        }
+        self.search_chunk(finder, &chunk, offset, &mut lines_before)?;
+        Ok(())
+    }
+
+    // 在由完整的行组成的 chunk 中查找, offset 是 chunk 在输入中的偏移, lines_before 是 chunk 之前的行数,
+    // 处理完之后加上 chunk 中的行数. sink 要求停止时返回 false
+    fn search_chunk(
+        &mut self,
+        finder: &Horspool,
+        chunk: &[u8],
+        offset: usize,
+        lines_before: &mut usize,
+    ) -> Result<bool, Error> {
+        let count_newlines = |bytes: &[u8]| bytes.iter().filter(|&&b| b == b'\n').count();
+        let len = finder.needle().len();
+        let mut pos = 0;
+        let mut counted = 0;
+        while let Some(hit) = finder.find_at(chunk, pos) {
+            let (start, end, next) = literal::line_around(chunk, hit);
+            *lines_before += count_newlines(&chunk[counted..start]);
+            counted = start;
+            // 匹配用到了行尾 \r\n 中的 \r 时, 逐行搜索是不会匹配的, 继续往后找
+            if hit + len > end {
+                pos = hit + 1;
+                continue;
+            }
+            // 有损解码后再找一次, 得到的区间才是解码后的行内的位置
+            let line = String::from_utf8_lossy(&chunk[start..end]);
+            if let Some(found) = finder.find(line.as_bytes()) {
+                let number = *lines_before + 1;
+                let found = [(found, found + len)];
+                if !self.line(number, offset + start, &line, &found, || 0)? {
+                    return Ok(false);
+                }
+            }
+            pos = next;
+        }
+        *lines_before += count_newlines(&chunk[counted..]);
+        Ok(true)
+    }
+
+    // -U: 匹配可以跨行, 所以先把整个输入读进内存, 在整个文本上查找.
+    // 匹配覆盖到的每一行都有匹配, 它的 found 是匹配落在这一行中的部分 (不包括换行符)
+    fn search_multiline<M: Matcher + ?Sized, R: BufRead>(
+        &mut self,
+        matcher: &M,
+        mut reader: R,
+    ) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|err| self.read_error(err))?;
+        let text = String::from_utf8_lossy(&bytes);
+        let mut spans = Vec::new();
+        matcher.find_into(&text, true, &mut spans);
+        // spans 按位置排列并且互不重叠, first 是第一个还没有在当前行之前结束的匹配
+        let mut first = 0;
+        let mut found = Vec::new();
+        for (index, (offset, line)) in printer::lines(&text).enumerate() {
+            let end = offset + line.len();
+            let next_line = offset
+                + text[offset..]
+                    .find('\n')
+                    .map_or(text.len() - offset, |i| i + 1);
+            // 非空的匹配恰好在行首结束时不算覆盖了这一行, 行首的空匹配算
+            while spans
+                .get(first)
+                .is_some_and(|&(s, e)| e < offset || (e == offset && s < e))
+            {
+                first += 1;
+            }
+            let covering = spans[first..].iter().take_while(|&&(s, _)| {
+                s < next_line || (s == next_line && next_line == text.len())
+            });
+            found.clear();
+            found.extend(covering.map(|&(s, e)| {
+                let start = s.clamp(offset, end);
+                (start - offset, e.clamp(start, end) - offset)
+            }));
+            let score = || matcher.score(&text, &spans[first..]);
+            if !self.line(index + 1, offset, line, &found, score)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::literal::Horspool;
+
+    // 把收到的事件记成字符串, 方便比较
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Sink for Events {
+        fn begin(&mut self, path: Option<&Path>) -> Result<(), Error> {
+            self.0.push(format!("begin {:?}", path));
+            Ok(())
+        }
+
+        fn matched(&mut self, line: &SinkLine) -> Result<bool, Error> {
+            self.0.push(format!(
+                "{}:{}:{}:{:?}",
+                line.line_number, line.byte_offset, line.line, line.matches
+            ));
+            Ok(line.line_number < 9)
+        }
+
+        fn context(&mut self, line: &SinkLine, kind: ContextKind) -> Result<bool, Error> {
+            self.0
+                .push(format!("{}-{:?}-{}", line.line_number, kind, line.line));
+            Ok(true)
+        }
+
+        fn finish(&mut self, selected: usize) -> Result<(), Error> {
+            self.0.push(format!("finish {}", selected));
+            Ok(())
+        }
+
+        fn binary(&mut self) -> Result<bool, Error> {
+            self.0.push(String::from("binary"));
+            Ok(true)
+        }
+    }
+
+    const CONTENTS: &str = "a\nb x\nc\nd\ne\nf x x\r\ng\nh\ni x\nj x\n";
+
+    #[test]
+    fn test_events_with_context() {
+        let searcher = Searcher {
+            before_context: 1,
+            after_context: 1,
+            ..Searcher::default()
+        };
+        let mut events = Events::default();
+        let selected = searcher
+            .search(&Horspool::new(b"x"), CONTENTS.as_bytes(), None, &mut events)
+            .unwrap();
+        // 第 9 行之后 sink 要求停止, 第 10 行没有被读取
+        assert_eq!(selected, 3);
+        assert_eq!(
+            events.0,
+            [
+                "begin None",
+                "1-Before-a",
+                "2:2:b x:[(2, 3)]",
+                "3-After-c",
+                "5-Before-e",
+                "6:12:f x x:[(2, 3), (4, 5)]",
+                "7-After-g",
+                "8-Before-h",
+                "9:23:i x:[(2, 3)]",
+                "finish 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invert_and_errors() {
+        let searcher = Searcher {
+            invert: true,
+            ..Searcher::default()
+        };
+        let mut events = Events::default();
+        let selected = searcher
+            .search(
+                &Horspool::new(b"x"),
+                "a\nb x\n".as_bytes(),
+                None,
+                &mut events,
+            )
+            .unwrap();
+        assert_eq!(selected, 1);
+        assert_eq!(events.0, ["begin None", "1:0:a:[]", "finish 1"]);
+        // 只实现了 Read 的输入
+        let reader = io::repeat(b'x').take(3);
+        let selected = Searcher::default()
+            .search_read(&Horspool::new(b"x"), reader, None, Events::default())
+            .unwrap();
+        assert_eq!(selected, 1);
+        let err = searcher
+            .search_path(
+                &Horspool::new(b"x"),
+                Path::new("no/such/file"),
+                Events::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::File { .. }));
+    }
+
+    #[test]
+    fn test_binary_decompress_and_multiline() {
+        let run = |searcher: Searcher, matcher: &dyn Matcher, input: &[u8]| {
+            let mut events = Events::default();
+            searcher.search(matcher, input, None, &mut events).unwrap();
+            events.0
+        };
+        let x = Horspool::new(b"x");
+        // 二进制输入先通知 sink, without-match 时不交给 sink 任何行
+        assert_eq!(
+            run(Searcher::default(), &x, b"a x\0\n"),
+            ["begin None", "binary", "1:0:a x\0:[(2, 3)]", "finish 1"]
+        );
+        let searcher = Searcher {
+            binary_files: BinaryFiles::WithoutMatch,
+            ..Searcher::default()
+        };
+        assert_eq!(run(searcher, &x, b"a x\0\n"), ["begin None", "finish 0"]);
+        // gzip 压缩的 "one\n"
+        let gzip = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0xcf, 0x4b, 0xe5,
+            0x02, 0x00, 0x9f, 0xa8, 0x17, 0xf8, 0x04, 0x00, 0x00, 0x00,
+        ];
+        let searcher = Searcher {
+            decompress: true,
+            ..Searcher::default()
+        };
+        assert_eq!(
+            run(searcher, &Horspool::new(b"ne"), &gzip),
+            ["begin None", "1:0:one:[(1, 3)]", "finish 1"]
+        );
+        // 统计的是解压之后的字节数
+        let mut printer = printer::Printer::new(&Config::default(), Vec::new());
+        searcher
+            .search(&Horspool::new(b"ne"), &gzip[..], None, &mut printer)
+            .unwrap();
+        assert_eq!(printer.stats().bytes_scanned, 4);
+        // 匹配覆盖的每一行都是被选中的行
+        let searcher = Searcher {
+            multiline: true,
+            ..Searcher::default()
+        };
+        let re = crate::regex::Regex::new("b\nc").unwrap();
+        assert_eq!(
+            run(searcher, &re, b"a\nb\nc\n"),
+            ["begin None", "2:2:b:[(0, 1)]", "3:4:c:[(0, 1)]", "finish 2"]
+        );
+    }
+}
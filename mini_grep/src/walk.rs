@@ -0,0 +1,339 @@
+//! 递归遍历目录, 收集需要搜索的文件
+//!
+//! 默认跳过隐藏文件 (以 `.` 开头的文件和目录) 以及二进制文件, 可以通过 [`WalkOptions`] 打开.
+//! 符号链接指向的目录不会进入, 避免出现循环.
+//! 无法读取的目录不会让整个遍历失败, 而是记录下错误, 跳过它继续遍历.
+//!
+//! 默认遵守 `.gitignore`, `.ignore` 和全局的忽略文件 (见 [`crate::ignore`]), 被忽略的目录不会进入.
+//! `--include` / `--exclude` 和 `--type` 进一步按通配符过滤: 通配符中没有 `/` 时匹配文件名,
+//! 有 `/` 时匹配相对于搜索目录的路径.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::config::BinaryFiles;
+use crate::glob::Glob;
+use crate::ignore::{self, Ignore};
+use crate::inflate;
+use crate::{Config, Error};
+
+/// 判断二进制文件时只检查文件开头的这么多字节
+pub const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// `--type` 支持的文件类型, 以及每种类型对应的通配符
+pub const TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+    ),
+    ("css", &["*.css", "*.scss"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// 文件类型对应的通配符, 不认识的类型返回 None
+pub fn type_globs(name: &str) -> Option<&'static [&'static str]> {
+    TYPES
+        .iter()
+        .find(|(type_name, _)| *type_name == name)
+        .map(|(_, globs)| *globs)
+}
+
+/// 遍历选项
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// 是否包含隐藏文件和隐藏目录
+    pub hidden: bool,
+    /// 是否包含二进制文件
+    pub binary: bool,
+    /// 是否解压 gzip / zlib 文件, 为 true 时按解压后的内容判断是否是二进制文件
+    pub decompress: bool,
+    /// 是否不读取忽略文件, 搜索所有文件
+    pub no_ignore: bool,
+    /// 全局的忽略文件, 为 None 时不读取, 见 [`ignore::global_excludes_file`]
+    pub global_ignore: Option<PathBuf>,
+    /// 不为空时只搜索和其中任意一个匹配的文件
+    pub include: Vec<Glob>,
+    /// 跳过和其中任意一个匹配的文件和目录
+    pub exclude: Vec<Glob>,
+}
+
+impl WalkOptions {
+    /// 根据命令行参数构造, `--type` 展开成对应的 include 通配符
+    pub fn from_config(conf: &Config) -> Result<WalkOptions, Error> {
+        let globs = |list: &[String]| {
+            list.iter()
+                .map(|g| Glob::new(g))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let mut include = globs(&conf.include)?;
+        for name in &conf.types {
+            let patterns = type_globs(name).ok_or_else(|| {
+                Error::Args(format!("unknown file type '{}', see --type-list", name))
+            })?;
+            for pattern in patterns {
+                include.push(Glob::new(pattern)?);
+            }
+        }
+        Ok(WalkOptions {
+            hidden: conf.hidden,
+            binary: conf.binary || conf.binary_files == BinaryFiles::Text,
+            decompress: conf.decompress,
+            no_ignore: conf.no_ignore,
+            global_ignore: if conf.no_ignore {
+                None
+            } else {
+                ignore::global_excludes_file()
+            },
+            include,
+            exclude: globs(&conf.exclude)?,
+        })
+    }
+
+    // 通配符中有 `/` 时匹配相对于搜索目录的路径, 否则只匹配文件名
+    fn matches_any(globs: &[Glob], root: &Path, path: &Path) -> bool {
+        globs.iter().any(|glob| {
+            let target = if glob.as_str().contains('/') {
+                path.strip_prefix(root).unwrap_or(path)
+            } else {
+                Path::new(path.file_name().unwrap_or_default())
+            };
+            glob.is_match(&target.to_string_lossy())
+        })
+    }
+}
+
+/// 返回 root 下所有需要搜索的文件, 按路径排序, 保证输出顺序稳定
+///
+/// root 本身是文件时直接返回它, 用户明确指定的文件不做任何过滤.
+/// 同时返回遍历中遇到的错误, 例如没有权限读取的目录
+pub fn files(root: &Path, opts: &WalkOptions) -> (Vec<PathBuf>, Vec<Error>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    if !root.is_dir() {
+        files.push(root.to_path_buf());
+    } else {
+        let mut walker = Walker {
+            root,
+            opts,
+            ignore: if opts.no_ignore {
+                Ignore::default()
+            } else {
+                Ignore::new(root, opts.global_ignore.as_deref())
+            },
+            files: &mut files,
+            errors: &mut errors,
+        };
+        if let Err(err) = walker.visit(root) {
+            errors.push(Error::file(root, err));
+        }
+    }
+    (files, errors)
+}
+
+struct Walker<'a> {
+    root: &'a Path,
+    opts: &'a WalkOptions,
+    // 当前目录以及所有上级目录的忽略规则
+    ignore: Ignore,
+    files: &'a mut Vec<PathBuf>,
+    errors: &'a mut Vec<Error>,
+}
+
+impl Walker<'_> {
+    // 打开 dir 本身失败时返回错误. 读不了的目录项和子目录的错误记录在 errors 中, 其他目录项照常遍历
+    fn visit(&mut self, dir: &Path) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(err) => self.errors.push(Error::file(dir, err)),
+            }
+        }
+        entries.sort_by_key(|entry| entry.file_name());
+        let pushed = !self.opts.no_ignore && self.ignore.push_dir(dir);
+        self.visit_entries(entries);
+        if pushed {
+            self.ignore.pop();
+        }
+        Ok(())
+    }
+
+    fn visit_entries(&mut self, entries: Vec<fs::DirEntry>) {
+        let opts = self.opts;
+        for entry in entries {
+            let path = entry.path();
+            if !opts.hidden && is_hidden(&path) {
+                continue;
+            }
+            // file_type 不会跟随符号链接, 指向目录的链接既不是 dir 也不会被当成普通文件搜索
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(err) => {
+                    self.errors.push(Error::file(path, err));
+                    continue;
+                }
+            };
+            let is_dir = file_type.is_dir();
+            if self.ignore.is_ignored(&path, is_dir)
+                || WalkOptions::matches_any(&opts.exclude, self.root, &path)
+            {
+                continue;
+            }
+            if is_dir {
+                if let Err(err) = self.visit(&path) {
+                    self.errors.push(Error::file(path, err));
+                }
+            } else if file_type.is_file() {
+                if !opts.include.is_empty()
+                    && !WalkOptions::matches_any(&opts.include, self.root, &path)
+                {
+                    continue;
+                }
+                // 读不了的文件先留在列表里, 搜索它的时候再报告错误
+                if opts.binary || !is_binary_file(&path, opts.decompress).unwrap_or(false) {
+                    self.files.push(path);
+                }
+            }
+        }
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+pub(crate) fn is_binary_file(path: &Path, decompress: bool) -> io::Result<bool> {
+    let mut buf = Vec::with_capacity(BINARY_SNIFF_LEN);
+    let reader = BufReader::new(File::open(path)?);
+    let reader: Box<dyn BufRead> = if decompress {
+        inflate::auto(reader)?
+    } else {
+        Box::new(reader)
+    };
+    reader.take(BINARY_SNIFF_LEN as u64).read_to_end(&mut buf)?;
+    Ok(is_binary(&buf))
+}
+
+/// 和 git, grep 一样的简单判断: 含有 NUL 字节的就是二进制内容
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_files() {
+        let root = env::temp_dir().join(format!("mini_grep_walk_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::write(root.join("src/a.rs"), "a").unwrap();
+        fs::write(root.join("src/nested/c.rs"), "c").unwrap();
+        fs::write(root.join(".hidden"), "h").unwrap();
+        fs::write(root.join(".git/config"), "g").unwrap();
+        fs::write(root.join("image.png"), b"\x89PNG\x00\x01").unwrap();
+
+        let relative = |opts: WalkOptions| -> Vec<String> {
+            let (files, errors) = files(&root, &opts);
+            assert!(errors.is_empty());
+            files
+                .iter()
+                .map(|p| p.strip_prefix(&root).unwrap().display().to_string())
+                .collect()
+        };
+        assert_eq!(
+            relative(WalkOptions::default()),
+            vec!["b.txt", "src/a.rs", "src/nested/c.rs"]
+        );
+        assert_eq!(
+            relative(WalkOptions {
+                hidden: true,
+                binary: true,
+                ..WalkOptions::default()
+            }),
+            vec![
+                ".git/config",
+                ".hidden",
+                "b.txt",
+                "image.png",
+                "src/a.rs",
+                "src/nested/c.rs"
+            ]
+        );
+        // 明确指定的文件即使是隐藏文件也会被搜索
+        let hidden = root.join(".hidden");
+        assert_eq!(files(&hidden, &WalkOptions::default()).0, vec![hidden]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_ignore_and_globs() {
+        let root = env::temp_dir().join(format!("mini_grep_ignore_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n/docs/*.md\n").unwrap();
+        fs::write(root.join("src/.gitignore"), "gen/\n!keep.log\n").unwrap();
+        fs::write(root.join(".ignore"), "!docs/README.md\n").unwrap();
+        fs::write(root.join("target/debug/out.rs"), "o").unwrap();
+        fs::write(root.join("app.log"), "l").unwrap();
+        fs::write(root.join("main.rs"), "m").unwrap();
+        fs::write(root.join("src/lib.rs"), "l").unwrap();
+        fs::write(root.join("src/keep.log"), "k").unwrap();
+        fs::write(root.join("src/gen/g.rs"), "g").unwrap();
+        fs::write(root.join("docs/guide.md"), "g").unwrap();
+        fs::write(root.join("docs/README.md"), "r").unwrap();
+
+        let relative = |opts: WalkOptions| -> Vec<String> {
+            files(&root, &opts)
+                .0
+                .iter()
+                .map(|p| p.strip_prefix(&root).unwrap().display().to_string())
+                .collect()
+        };
+        assert_eq!(
+            relative(WalkOptions::default()),
+            vec!["docs/README.md", "main.rs", "src/keep.log", "src/lib.rs"]
+        );
+        let mut conf = Config {
+            types: vec!["rust".to_string()],
+            exclude: vec!["src/**".to_string()],
+            ..Config::default()
+        };
+        let mut opts = WalkOptions::from_config(&conf).unwrap();
+        opts.global_ignore = None;
+        assert_eq!(relative(opts), vec!["main.rs"]);
+
+        conf.types.clear();
+        conf.include = vec!["*.rs".to_string()];
+        conf.no_ignore = true;
+        assert_eq!(
+            relative(WalkOptions::from_config(&conf).unwrap()),
+            vec!["main.rs", "target/debug/out.rs"]
+        );
+        conf.types = vec!["cobol".to_string()];
+        assert!(WalkOptions::from_config(&conf).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}